@@ -1,24 +1,176 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
 
-    #[arg(short, long, value_name = "FILE")]
+    /// Config file to use instead of the default locations
+    #[arg(short, long, value_name = "FILE", global = true)]
     pub config: Option<PathBuf>,
 
-    #[arg(short, long, value_name = "FILE")]
+    /// Manager folder to use instead of the one in the config
+    #[arg(short, long, value_name = "DIR", global = true)]
     pub manager: Option<PathBuf>,
 
-    #[arg(short, long, default_value_t=false)]
-    pub from: bool,
+    /// Profile to use instead of $DOTFILES_PROFILE or the one matching the hostname
+    #[arg(short, long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
 
-    #[arg(short, long, default_value_t=false)]
+    /// Print what would change without touching any files
+    #[arg(short, long, default_value_t=false, global = true)]
     pub dry: bool,
+
+    /// Also print unchanged and in-sync files
+    #[arg(short, long, default_value_t=false, global = true)]
+    pub verbose: bool,
+
+    /// Copy from the manager folder when no subcommand is given, same as `apply`
+    #[arg(short, long, default_value_t=false, hide = true)]
+    pub from: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Copy dotfiles from the system into the manager folder
+    Collect {
+        /// Overwrite files that changed since the last sync
+        #[arg(long, default_value_t=false)]
+        force: bool,
+
+        /// Don't commit the collected files when the manager folder is a git repository
+        #[arg(long, default_value_t=false)]
+        no_commit: bool,
+
+        #[command(flatten)]
+        selection: Selection,
+    },
+
+    /// Copy dotfiles from the manager folder onto the system
+    Apply {
+        /// Overwrite files that changed since the last sync
+        #[arg(long, default_value_t=false)]
+        force: bool,
+
+        /// Fetch and rebase the manager folder from its git remote first
+        #[arg(long, default_value_t=false)]
+        pull: bool,
+
+        #[command(flatten)]
+        selection: Selection,
+    },
+
+    /// Show whether the system and manager copies of each dotfile differ
+    Status {
+        /// Print every entry as tab-separated state, system path and manager path for scripts
+        #[arg(long, default_value_t=false)]
+        porcelain: bool,
+
+        #[command(flatten)]
+        selection: Selection,
+    },
+
+    /// Show a unified diff of what syncing would change
+    Diff {
+        /// Which sync to preview
+        #[arg(long, value_enum, default_value_t=Direction::Apply)]
+        direction: Direction,
+
+        /// Only show a summary of changed lines per file
+        #[arg(long, default_value_t=false)]
+        stat: bool,
+
+        #[command(flatten)]
+        selection: Selection,
+    },
+
+    /// Start managing a file or directory by copying it into the manager folder and adding it to the config
+    Add {
+        /// File or directory on the system to add
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+
+        /// Where to put it in the manager folder, defaults to its path relative to $HOME
+        #[arg(long, value_name = "PATH")]
+        manager_path: Option<PathBuf>,
+
+        /// Name used to select the dotfile, defaults to the last part of the manager path
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+
+        /// Tags used to select the dotfile
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+    },
+
+    /// Stop managing a dotfile, removing it from the config and leaving the system copy in place
+    #[command(alias = "remove")]
+    Forget {
+        /// Name or path of the dotfile to forget
+        #[arg(value_name = "NAME|PATH")]
+        target: String,
+
+        /// Also delete the copy in the manager folder, it is moved into a backup
+        #[arg(long, default_value_t=false)]
+        delete: bool,
+
+        /// Restore the system copy from the backup taken before the dotfile first overwrote it
+        #[arg(long, default_value_t=false)]
+        restore_original: bool,
+    },
+
+    /// List or restore backups taken before files were overwritten
+    Restore {
+        /// Backup to restore from, defaults to the most recent one
+        #[arg(long, value_name = "TIMESTAMP")]
+        timestamp: Option<String>,
+
+        /// List backups instead of restoring one
+        #[arg(short, long, default_value_t=false)]
+        list: bool,
+
+        #[command(flatten)]
+        selection: Selection,
+    },
+}
+
+
+#[derive(Args, Clone, Default)]
+pub struct Selection {
+    /// Names of dotfiles, or paths they are under, to operate on instead of all of them
+    #[arg(value_name = "NAME|PATH")]
+    pub targets: Vec<String>,
+
+    /// Only operate on dotfiles with this tag
+    #[arg(short, long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+}
+
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// System to manager folder
+    Collect,
+    /// Manager folder to system
+    Apply,
 }
 
 pub fn parse_args() -> Cli {
-    Cli::parse()
+
+    let cli = Cli::parse();
+
+    // Only meant for the old `dotfile -f`, with a subcommand it would be silently ignored
+    if cli.from && cli.command.is_some() {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--from cannot be used with a subcommand, use `apply` instead")
+            .exit()
+    }
+
+    cli
 }
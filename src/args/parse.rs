@@ -1,4 +1,68 @@
+use std::env;
+use std::path::PathBuf;
 
+use crate::args::arg::{Cli, Command, Direction, Selection};
+use crate::dotfile::dot::ManagedDotfile;
 
 
 
+
+impl Cli {
+    pub fn get_command(&self) -> Command {
+
+        match &self.command {
+            Some(command) => command.clone(),
+            None if self.from => Command::Apply { force: false, pull: false, selection: Selection::default() },
+            None => Command::Collect { force: false, no_commit: false, selection: Selection::default() },
+        }
+    }
+}
+
+
+static NO_SELECTION: Selection = Selection { targets: Vec::new(), tags: Vec::new() };
+
+
+impl Command {
+    pub fn selection(&self) -> &Selection {
+
+        match self {
+            Command::Collect { selection, .. } => selection,
+            Command::Apply { selection, .. } => selection,
+            Command::Status { selection, .. } => selection,
+            Command::Diff { selection, .. } => selection,
+            Command::Restore { selection, .. } => selection,
+            Command::Add { .. } | Command::Forget { .. } => &NO_SELECTION,
+        }
+    }
+}
+
+
+impl Selection {
+    pub fn matches(&self, dotfile: &ManagedDotfile) -> bool {
+
+        let tag_matches = self.tags.is_empty() || self.tags.iter().any(|tag| dotfile.options.tags.contains(tag));
+
+        let target_matches = self.targets.is_empty() || self.targets.iter().any(|target| {
+            if *target == dotfile.options.name {
+                return true
+            }
+
+            let path = PathBuf::from(target);
+            let path = match env::current_dir() {
+                Ok(current_dir) if path.is_relative() => current_dir.join(path),
+                _ => path,
+            };
+
+            dotfile.system_dotfile.path().starts_with(&path) || dotfile.manager_dotfile.path().starts_with(&path)
+        });
+
+        tag_matches && target_matches
+    }
+}
+
+
+impl Direction {
+    pub fn to_system(&self) -> bool {
+        *self == Direction::Apply
+    }
+}
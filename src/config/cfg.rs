@@ -1,107 +1,541 @@
 use std::fs;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::error::Error;
 use std::fmt;
+use std::ops::Range;
 
-use toml::Table;
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use toml::{Spanned, Table};
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, ImDocument, Item, Value};
 
+use crate::config::expand;
+use crate::config::profile::Profile;
 use crate::dotfile::dot::{self, ManagedDotfile};
+use crate::fs::dir::SpecialFiles;
+use crate::template::vars::{self, Variables};
+use crate::vcs::git::GitSettings;
 
 
 
 
+#[derive(Clone)]
+pub struct DotfileEntry {
+    pub index: Option<usize>,
+    pub manager_path: PathBuf,
+    pub system_path: PathBuf,
+    pub options: dot::DotfileOptions,
+}
+
+impl DotfileEntry {
+    pub fn load(&self, manager_dir: &Path) -> Result<ManagedDotfile, dot::DotfileError> {
+        ManagedDotfile::new(manager_dir, self.manager_path.clone(), self.system_path.clone(), self.options.clone())
+    }
+}
+
+
+// A [[dotfiles]] entry as written in the config, spans are kept for values checked after parsing
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, expecting = "a dotfile table")]
+struct DotfileTable {
+    #[serde(alias = "manager_location")]
+    manager_path: Spanned<String>,
+    #[serde(alias = "system_location")]
+    system_path: Spanned<String>,
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    mode: Option<dot::DeployMode>,
+    #[serde(default)]
+    template: bool,
+    #[serde(default)]
+    ignore: Vec<String>,
+    mode_bits: Option<Spanned<toml::Value>>,
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default)]
+    follow_symlinks: bool,
+    special_files: Option<SpecialFiles>,
+}
+
+
+// Which [[dotfiles]] entry, key and line an error in the config points at
+#[derive(Debug)]
+pub struct DotfileLocation {
+    pub index: usize,
+    pub name: Option<String>,
+    pub key: Option<String>,
+    pub position: Option<(usize, usize)>,
+}
+
+impl DotfileLocation {
+    fn find(source: &str, index: usize, entry: &Value, span: Option<Range<usize>>) -> DotfileLocation {
+
+        let table = entry.as_inline_table();
+        let get_str = |key: &str| table.and_then(|table| table.get(key)).and_then(Value::as_str);
+
+        let name = match get_str("name") {
+            Some(name) => Some(String::from(name)),
+            None => get_str("manager_path")
+                .or_else(|| get_str("manager_location"))
+                .and_then(|path| Path::new(path).file_name()?.to_str())
+                .map(String::from),
+        };
+
+        let offset = span.map(|span| span.start);
+
+        // The key the error falls within, errors about the whole table (like a missing key) have none
+        let key = table.zip(offset).and_then(|(table, offset)| {
+            table
+                .iter()
+                .find(|(key, value)| {
+                    let start = table.key(key).and_then(|key| key.span()).map(|span| span.start);
+                    let end = value.span().map(|span| span.end);
+                    matches!((start, end), (Some(start), Some(end)) if start <= offset && offset < end)
+                })
+                .map(|(key, _)| String::from(key))
+        });
+
+        let position = offset.and_then(|offset| source.get(..offset)).map(|before| {
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            (line, column)
+        });
+
+        DotfileLocation { index, name, key, position }
+    }
+}
+
+impl fmt::Display for DotfileLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "Dotfile {}", self.index + 1)?;
+
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+
+        if let Some((line, column)) = self.position {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+
+        if let Some(key) = &self.key {
+            write!(f, ", key `{}`", key)?;
+        }
+
+        Ok(())
+    }
+}
+
+
 pub struct Config {
+    pub path: PathBuf,
     pub manager_dir: PathBuf,
-    pub dotfiles: Vec<Result<ManagedDotfile, ConfigParseError>>,
+    pub backup_dir: PathBuf,
+    pub state_dir: PathBuf,
+    pub git: GitSettings,
+    pub variables: Variables,
+    pub profile: Option<Profile>,
+    // Every dotfile in the config, the profile is applied when they are synced
+    pub dotfiles: Vec<Result<DotfileEntry, ConfigParseError>>,
 }
 
 impl Config {
-    pub fn parse(path: PathBuf) -> Result<Self, ConfigParseError> {
+    pub fn parse(path: PathBuf, manager_dir: Option<PathBuf>, profile: Option<String>) -> Result<Self, ConfigParseError> {
+
+        let (config_file, document) = Config::read_config(&path)?;
+
+        let manager_dir = match manager_dir {
+            Some(manager_dir) => manager_dir,
+            None => Config::get_manager_dir(&config_file)?,
+        };
 
-        let config_file = Config::read_config(path)?;
+        let state_dir = Config::get_state_dir()?;
 
-        let dotfiles = Config::get_dotfiles(&config_file)?;
+        let backup_dir = Config::get_backup_dir(&config_file, &state_dir)?;
 
-        let manager_dir = Config::get_manager_dir(&config_file)?;
+        let mode = match config_file.get("mode") {
+            Some(mode) => Config::parse_mode(mode)?,
+            None => dot::DeployMode::default(),
+        };
+
+        let git = Config::get_git_settings(&config_file)?;
+
+        let profile = Profile::find(&config_file, &document, profile)?;
+
+        let variables = Config::get_variables(&config_file, profile.as_ref())?;
 
-        Ok(Config{manager_dir, dotfiles})
+        let ignore = match config_file.get("ignore") {
+            Some(patterns) => Config::get_patterns(patterns)?,
+            None => Vec::new(),
+        };
+
+        let special_files = match config_file.get("special_files") {
+            Some(special_files) => Config::parse_special_files(special_files)?,
+            None => SpecialFiles::default(),
+        };
+
+        let dotfiles = Config::get_dotfiles(&document, mode, special_files, &ignore)?;
+
+        if let Some(profile) = &profile {
+            profile.check(&dotfiles)?;
+        }
+
+        Ok(Config{path, manager_dir, backup_dir, state_dir, git, variables, profile, dotfiles})
     }
 
 
-    fn read_config(path: PathBuf) -> Result<Table, ConfigParseError> {
+    pub fn state_file(&self) -> PathBuf {
+        self.state_dir.join("state.toml")
+    }
 
-        let file = fs::read(path)?;
 
-        let read_file = String::from_utf8(file)?;
-        
-        let config: Table = read_file.parse()?;
+    pub fn find(config_path: Option<PathBuf>) -> Result<PathBuf, ConfigParseError> {
 
-        Ok(config)
+        // An explicit config is never swapped for another, add and forget would rewrite the wrong file
+        let explicit = config_path.or_else(|| env::var_os("DOTFILES_CONFIG").filter(|path| !path.is_empty()).map(PathBuf::from));
 
+        if let Some(path) = explicit {
+            if !path.is_file() {
+                return Err(ConfigParseError::ConfigNotFoundError(vec![path]))
+            }
+            return Ok(path)
+        }
+
+        let mut candidates = Vec::new();
+
+        if let Some(xdg_config_home) = env::var_os("XDG_CONFIG_HOME") {
+            let xdg_config_dir = PathBuf::from(xdg_config_home).join("dotfiles");
+            candidates.push(xdg_config_dir.join("config"));
+            candidates.push(xdg_config_dir.join("config.toml"));
+        }
+
+        if let Some(home_dir) = env::var_os("HOME") {
+            candidates.push(PathBuf::from(home_dir).join(".config/dotfiles/config"));
+        }
+
+        match candidates.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(ConfigParseError::ConfigNotFoundError(candidates)),
+        }
     }
 
 
-    fn get_dotfiles(config: &Table) -> Result<Vec<Result<ManagedDotfile, ConfigParseError>>, ConfigParseError> {
+    fn read_config(path: &Path) -> Result<(Table, ImDocument<String>), ConfigParseError> {
+
+        let file = fs::read(path).map_err(|e| ConfigParseError::FileReadError(path.to_path_buf(), e))?;
 
-        let read_dotfiles = config.get("dotfiles");
+        let read_file = String::from_utf8(file).map_err(|e| ConfigParseError::FromUtfError(path.to_path_buf(), e))?;
         
-        let dotfiles = match read_dotfiles {
-            Some(dotfiles) => dotfiles,
-            None => return Err(ConfigParseError::DotfilesParseError),
+        let config: Table = read_file.parse().map_err(|e| ConfigParseError::TomlParseError(path.to_path_buf(), Box::new(e)))?;
+
+        let document = ImDocument::parse(read_file).map_err(|e| ConfigParseError::TomlEditError(path.to_path_buf(), Box::new(e)))?;
+
+        Ok((config, document))
+
+    }
+
+
+    pub fn append_dotfile(&self, entry: &DotfileEntry) -> Result<(), ConfigParseError> {
+
+        let mut document = self.read_document()?;
+
+        let path_str = |path: &Path| path.to_str().map(String::from).ok_or_else(|| ConfigParseError::InvalidPathError(path.to_path_buf()));
+
+        let mut dotfile_table = toml_edit::Table::new();
+
+        let home_dir = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+
+        let system_path = match entry.system_path.strip_prefix(&home_dir) {
+            Ok(relative_path) if home_dir.is_absolute() => Path::new("~").join(relative_path),
+            _ => entry.system_path.clone(),
+        };
+
+        dotfile_table["system_path"] = value(path_str(&system_path)?);
+        dotfile_table["manager_path"] = value(path_str(&entry.manager_path)?);
+
+        let default_name = entry.manager_path.file_name().and_then(|name| name.to_str());
+
+        if default_name != Some(entry.options.name.as_str()) {
+            dotfile_table["name"] = value(&entry.options.name);
+        }
+
+        if !entry.options.tags.is_empty() {
+            dotfile_table["tags"] = value(entry.options.tags.iter().collect::<Array>());
+        }
+
+        match document.get_mut("dotfiles") {
+            Some(Item::ArrayOfTables(dotfiles)) => dotfiles.push(dotfile_table),
+            Some(Item::Value(toml_edit::Value::Array(dotfiles))) if !dotfiles.is_empty() => {
+                dotfiles.push(dotfile_table.into_inline_table())
+            },
+            // Missing, or left empty by forget
+            Some(Item::Value(toml_edit::Value::Array(_))) | None => {
+                let mut dotfiles = ArrayOfTables::new();
+                dotfiles.push(dotfile_table);
+                document.insert("dotfiles", Item::ArrayOfTables(dotfiles));
+            },
+            Some(_) => return Err(ConfigParseError::DotfilesArrayParseError),
+        };
+
+        fs::write(&self.path, document.to_string()).map_err(|e| ConfigParseError::FileWriteError(self.path.clone(), e))?;
+
+        Ok(())
+    }
+
+
+    pub fn remove_dotfiles(&self, indices: &[usize]) -> Result<(), ConfigParseError> {
+
+        let mut document = self.read_document()?;
+
+        let mut indices = indices.to_vec();
+        indices.sort();
+        indices.dedup();
+
+        match document.get_mut("dotfiles") {
+            Some(Item::ArrayOfTables(dotfiles)) => {
+                let count = dotfiles.len();
+                for index in indices.into_iter().rev().filter(|index| *index < count) {
+                    dotfiles.remove(index);
+                }
+
+                // An empty [[dotfiles]] isn't written at all, keep the key so the config stays recognisable
+                if dotfiles.is_empty() {
+                    document.insert("dotfiles", value(Array::new()));
+                }
+            },
+            Some(Item::Value(toml_edit::Value::Array(dotfiles))) => {
+                let count = dotfiles.len();
+                for index in indices.into_iter().rev().filter(|index| *index < count) {
+                    dotfiles.remove(index);
+                }
+            },
+            _ => return Err(ConfigParseError::DotfilesArrayParseError),
+        };
+
+        fs::write(&self.path, document.to_string()).map_err(|e| ConfigParseError::FileWriteError(self.path.clone(), e))?;
+
+        Ok(())
+    }
+
+
+    fn read_document(&self) -> Result<DocumentMut, ConfigParseError> {
+
+        fs::read_to_string(&self.path)
+            .map_err(|e| ConfigParseError::FileReadError(self.path.clone(), e))?
+            .parse()
+            .map_err(|e| ConfigParseError::TomlEditError(self.path.clone(), Box::new(e)))
+    }
+
+
+    fn get_aliased<'a>(table: &'a Table, keys: &[&str]) -> Option<&'a toml::Value> {
+        keys.iter().find_map(|key| table.get(*key))
+    }
+
+
+    fn parse_mode(mode: &toml::Value) -> Result<dot::DeployMode, ConfigParseError> {
+        dot::DeployMode::deserialize(mode.clone()).map_err(|_| ConfigParseError::InvalidModeError(mode.to_string()))
+    }
+
+
+    fn parse_special_files(special_files: &toml::Value) -> Result<SpecialFiles, ConfigParseError> {
+        SpecialFiles::deserialize(special_files.clone()).map_err(|_| ConfigParseError::InvalidSpecialFilesError(special_files.to_string()))
+    }
+
+
+    // Integers are only taken as written with 0o, a plain 600 would be read as decimal
+    fn parse_mode_bits(mode_bits: &toml::Value, raw: &str) -> Result<u32, ConfigParseError> {
+
+        let parsed = match mode_bits {
+            toml::Value::String(string) => u32::from_str_radix(string.trim_start_matches("0o"), 8).ok(),
+            toml::Value::Integer(integer) if raw.starts_with("0o") => u32::try_from(*integer).ok(),
+            _ => None,
         };
 
-        let dotfile_iter = match dotfiles.as_array() {
-            Some(dotfiles) => dotfiles.iter(), 
-            None => return Err(ConfigParseError::DotfilesArrayParseError),
+        match parsed {
+            Some(bits) if bits <= 0o7777 => Ok(bits),
+            _ => Err(ConfigParseError::InvalidModeBitsError(String::from(raw))),
+        }
+    }
+
+
+    fn get_patterns(patterns: &toml::Value) -> Result<Vec<String>, ConfigParseError> {
+
+        patterns
+            .as_array()
+            .ok_or(ConfigParseError::InvalidIgnorePatternsError)?
+            .iter()
+            .map(|pattern| pattern.as_str().map(String::from))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ConfigParseError::InvalidIgnorePatternsError)
+    }
+
+
+    fn get_dotfiles(document: &ImDocument<String>, default_mode: dot::DeployMode, default_special_files: SpecialFiles, global_ignore: &[String]) -> Result<Vec<Result<DotfileEntry, ConfigParseError>>, ConfigParseError> {
+
+        // Both [[dotfiles]] and an inline array of tables are accepted, a config without any is empty
+        let entries: Vec<(Value, Option<Range<usize>>)> = match document.get("dotfiles") {
+            Some(Item::ArrayOfTables(tables)) => tables
+                .iter()
+                .map(|table| (Value::InlineTable(table.clone().into_inline_table()), table.span()))
+                .collect(),
+            Some(Item::Value(Value::Array(array))) => array
+                .iter()
+                .map(|value| (value.clone(), value.span()))
+                .collect(),
+            Some(_) => return Err(ConfigParseError::DotfilesArrayParseError),
+            None => Vec::new(),
         };
 
+        let dotfiles = entries.into_iter().enumerate().map(|(index, (entry, entry_span))| {
+
+                let invalid = |span: Option<Range<usize>>, message: String| {
+                    let location = DotfileLocation::find(document.raw(), index, &entry, span.or(entry_span.clone()));
+                    ConfigParseError::InvalidDotfileError(location, message)
+                };
+
+                let table = DotfileTable::deserialize(entry.clone().into_deserializer())
+                    .map_err(|e| invalid(e.span(), String::from(e.message())))?;
+
+                let manager_path = PathBuf::from(table.manager_path.get_ref());
+
+                let name = match table.name {
+                    Some(name) => name,
+                    None => match manager_path.file_name().and_then(|name| name.to_str()) {
+                        Some(name) => String::from(name),
+                        None => return Err(invalid(Some(table.manager_path.span()), String::from("manager_path has no file name to name the dotfile by, set `name`"))),
+                    },
+                };
+
+                let system_path = expand::expand_path(table.system_path.get_ref())
+                    .map_err(|variable| invalid(Some(table.system_path.span()), format!("variable ${} is not set", variable)))?;
 
-        let dotfiles = dotfile_iter.map(|dotfile| {
+                let mode_bits = match &table.mode_bits {
+                    Some(mode_bits) => {
+                        let raw = document.raw().get(mode_bits.span()).unwrap_or_default();
+                        Some(Config::parse_mode_bits(mode_bits.get_ref(), raw).map_err(|e| invalid(Some(mode_bits.span()), e.to_string()))?)
+                    },
+                    None => None,
+                };
 
-                let dotfile_table = match dotfile.as_table() {
-                    Some(table) => table,
-                    None => return Err(ConfigParseError::DotfilesParseError),
+                let mode = table.mode.unwrap_or(default_mode);
+
+                // A hard link shares its mode with the manager copy, which would be changed too
+                if let (Some(mode_bits), dot::DeployMode::Hardlink) = (&table.mode_bits, mode) {
+                    return Err(invalid(Some(mode_bits.span()), String::from("mode_bits can't be used with the hardlink mode, the manager copy shares its permissions")))
+                }
+
+                let options = dot::DotfileOptions {
+                    name,
+                    tags: table.tags,
+                    mode,
+                    template: table.template,
+                    global_ignore: global_ignore.to_vec(),
+                    ignore: table.ignore,
+                    mode_bits,
+                    mirror: table.mirror,
+                    follow_symlinks: table.follow_symlinks,
+                    special_files: table.special_files.unwrap_or(default_special_files),
                 };
 
-                let manager_path = PathBuf::from(
-                    match dotfile_table.get("manager_path") {
-                        Some(path) => path.as_str().expect("Invalid character in dotfile path"),
-                        None => return Err(ConfigParseError::DotfilesTableParseError),
-                    }
-                );
-
-                let system_path = PathBuf::from(
-                    match dotfile_table.get("system_path") {
-                        Some(path) => path.as_str().expect("Invalid character in dotfile path"),
-                        None => return Err(ConfigParseError::DotfilesTableParseError),
-                    }
-                );
-
-                Ok(ManagedDotfile::new(manager_path, system_path)?)
+                Ok(DotfileEntry { index: Some(index), manager_path, system_path, options })
         });
 
         Ok(dotfiles.collect())
     }
 
 
-    fn get_manager_dir(config: &Table) -> Result<PathBuf, ConfigParseError> {
+    fn get_git_settings(config: &Table) -> Result<GitSettings, ConfigParseError> {
 
-        let home_dir = PathBuf::from(env::var("HOME")?);
+        let mut settings = GitSettings::default();
 
-        let manager_dir = if config.contains_key("manager_directory") {
-            match config.get("manager_directory").unwrap().as_str() {
-                Some(string) if PathBuf::from(string).is_absolute() => PathBuf::from(string),
-                Some(string) => home_dir.join(PathBuf::from(string)),
-                None => return Err(ConfigParseError::InvalidManagerDirectoryError),
-            }
-        } else {
-            home_dir.join(PathBuf::from(".dotfiles"))
+        let git_table = match config.get("git") {
+            Some(git) => git.as_table().ok_or(ConfigParseError::InvalidGitSettingsError)?,
+            None => return Ok(settings),
+        };
+
+        if let Some(auto_commit) = git_table.get("auto_commit") {
+            settings.auto_commit = auto_commit.as_bool().ok_or(ConfigParseError::InvalidGitSettingsError)?;
+        }
+
+        if let Some(pull) = git_table.get("pull") {
+            settings.pull = pull.as_bool().ok_or(ConfigParseError::InvalidGitSettingsError)?;
+        }
+
+        if let Some(remote) = git_table.get("remote") {
+            settings.remote = String::from(remote.as_str().ok_or(ConfigParseError::InvalidGitSettingsError)?);
+        }
+
+        if let Some(branch) = git_table.get("branch") {
+            settings.branch = Some(String::from(branch.as_str().ok_or(ConfigParseError::InvalidGitSettingsError)?));
+        }
+
+        Ok(settings)
+    }
+
+
+    fn get_variables(config: &Table, profile: Option<&Profile>) -> Result<Variables, ConfigParseError> {
+
+        let mut variables = Variables::facts();
+
+        if let Some(values) = config.get("variables") {
+            variables.merge(values.as_table().ok_or(ConfigParseError::InvalidVariablesError)?);
+        }
+
+        if let Some(profile) = profile {
+            variables.merge(&profile.variables);
+        }
+
+        let host_values = config
+            .get("hosts")
+            .and_then(|hosts| hosts.get(vars::hostname()))
+            .and_then(|host| host.get("variables"));
+
+        if let Some(values) = host_values {
+            variables.merge(values.as_table().ok_or(ConfigParseError::InvalidVariablesError)?);
+        }
+
+        Ok(variables)
+    }
+
+
+    fn get_manager_dir(config: &Table) -> Result<PathBuf, ConfigParseError> {
+
+        let manager_dir = match Config::get_aliased(config, &["manager_directory", "manager_dir"]) {
+            Some(manager_dir) => manager_dir.as_str().ok_or(ConfigParseError::InvalidManagerDirectoryError)?,
+            None => ".dotfiles",
         };
 
+        let manager_dir = expand::expand_path(manager_dir)
+            .map_err(|variable| ConfigParseError::UndefinedVariableError(variable, String::from("manager_directory")))?;
+
         Ok(manager_dir)
     }
 
+
+    fn get_state_dir() -> Result<PathBuf, ConfigParseError> {
+
+        let state_home = match env::var_os("XDG_STATE_HOME") {
+            Some(state_home) => PathBuf::from(state_home),
+            None => PathBuf::from(env::var("HOME").map_err(|e| ConfigParseError::ConfigEnvVariableError(String::from("HOME"), e))?).join(".local/state"),
+        };
+
+        Ok(state_home.join("dotfiles"))
+    }
+
+
+    fn get_backup_dir(config: &Table, state_dir: &Path) -> Result<PathBuf, ConfigParseError> {
+
+        let backup_dir = match Config::get_aliased(config, &["backup_directory", "backup_dir"]) {
+            Some(backup_dir) => expand::expand_path(backup_dir.as_str().ok_or(ConfigParseError::InvalidBackupDirectoryError)?)
+                .map_err(|variable| ConfigParseError::UndefinedVariableError(variable, String::from("backup_directory")))?,
+            None => state_dir.join("backups"),
+        };
+
+        Ok(backup_dir)
+    }
+
 }
 
 
@@ -109,80 +543,116 @@ impl Config {
 
 #[derive(Debug)]
 pub enum ConfigParseError {
-    FileReadError(std::io::Error),
-    FromUtfError(std::string::FromUtf8Error),
-    TomlParseError(toml::de::Error),
-    ConfigEnvVariableError(std::env::VarError),
-    DotfilesCreateError(dot::DotfileError),
-    DotfilesParseError,
+    FileReadError(PathBuf, std::io::Error),
+    FileWriteError(PathBuf, std::io::Error),
+    FromUtfError(PathBuf, std::string::FromUtf8Error),
+    TomlParseError(PathBuf, Box<toml::de::Error>),
+    TomlEditError(PathBuf, Box<toml_edit::TomlError>),
+    ConfigEnvVariableError(String, std::env::VarError),
+    ConfigNotFoundError(Vec<PathBuf>),
     DotfilesArrayParseError,
-    DotfilesTableParseError,
+    InvalidDotfileError(DotfileLocation, String),
     InvalidManagerDirectoryError,
+    InvalidBackupDirectoryError,
+    InvalidModeError(String),
+    InvalidGitSettingsError,
+    InvalidVariablesError,
+    InvalidIgnorePatternsError,
+    InvalidModeBitsError(String),
+    InvalidSpecialFilesError(String),
+    InvalidPathError(PathBuf),
+    UndefinedVariableError(String, String),
+    InvalidProfileError(String),
+    ProfileNotFoundError(String),
+    UnknownProfileDotfileError(String, String),
 }
 
-impl Error for ConfigParseError {}
+impl Error for ConfigParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigParseError::FileReadError(_, io_error) | ConfigParseError::FileWriteError(_, io_error) => Some(io_error),
+            ConfigParseError::FromUtfError(_, utf_error) => Some(utf_error),
+            ConfigParseError::TomlParseError(_, parse_error) => Some(parse_error.as_ref()),
+            ConfigParseError::TomlEditError(_, edit_error) => Some(edit_error.as_ref()),
+            ConfigParseError::ConfigEnvVariableError(_, env_error) => Some(env_error),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for ConfigParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ConfigParseError::FileReadError(io_error) => {
-                write!(f, "{}", io_error)
+            ConfigParseError::FileReadError(path, _) => {
+                write!(f, "Failed to read config {}", path.display())
+            },
+            ConfigParseError::FileWriteError(path, _) => {
+                write!(f, "Failed to write config {}", path.display())
             },
-            ConfigParseError::FromUtfError(utf_error) => {
-                write!(f, "{}", utf_error)
+            ConfigParseError::FromUtfError(path, _) => {
+                write!(f, "Config {} is not valid UTF-8", path.display())
             },
-            ConfigParseError::TomlParseError(parse_error) => {
-                write!(f, "{}", parse_error)
+            ConfigParseError::TomlParseError(path, _) => {
+                write!(f, "Config {} is not valid TOML", path.display())
             },
-            ConfigParseError::DotfilesCreateError(create_error) => {
-                write!(f, "{}", create_error)
+            ConfigParseError::TomlEditError(path, _) => {
+                write!(f, "Config {} is not valid TOML", path.display())
             },
-            ConfigParseError::ConfigEnvVariableError(env_error) => {
-                write!(f, "{}", env_error)
+            ConfigParseError::ConfigEnvVariableError(variable, _) => {
+                write!(f, "Failed to read ${}", variable)
             }
-            ConfigParseError::DotfilesParseError => {
-                write!(f, "Dotfiles section not found in config file")
+            ConfigParseError::ConfigNotFoundError(tried_paths) => {
+                write!(f, "Config file not found, tried:")?;
+                for path in tried_paths.iter() {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
             },
             ConfigParseError::DotfilesArrayParseError => {
                 write!(f, "Dotfiles is not a valid array, Hint: use [[dotfiles]]")
             },
-            ConfigParseError::DotfilesTableParseError => {
-                write!(f, "Dotfile table is not valid")
+            ConfigParseError::InvalidDotfileError(location, message) => {
+                write!(f, "{}: {}", location, message)
             },
             ConfigParseError::InvalidManagerDirectoryError => {
                 write!(f, "Manager directory setting in config is not valid")
-            }
+            },
+            ConfigParseError::InvalidBackupDirectoryError => {
+                write!(f, "Backup directory setting in config is not valid")
+            },
+            ConfigParseError::InvalidGitSettingsError => {
+                write!(f, "Git settings in config are not valid")
+            },
+            ConfigParseError::InvalidVariablesError => {
+                write!(f, "Variables in config are not a valid table")
+            },
+            ConfigParseError::InvalidIgnorePatternsError => {
+                write!(f, "Ignore patterns in config are not a list of strings")
+            },
+            ConfigParseError::InvalidModeBitsError(mode_bits) => {
+                write!(f, "Mode bits {} are not valid, expected an octal mode like \"0600\" or 0o600", mode_bits)
+            },
+            ConfigParseError::InvalidPathError(path) => {
+                write!(f, "Path {} is not valid UTF-8", path.display())
+            },
+            ConfigParseError::UndefinedVariableError(variable, entry) => {
+                write!(f, "Variable ${} used by {} is not set", variable, entry)
+            },
+            ConfigParseError::InvalidProfileError(profile) => {
+                write!(f, "Profile {} in config is not valid", profile)
+            },
+            ConfigParseError::ProfileNotFoundError(profile) => {
+                write!(f, "Profile {} not found in config", profile)
+            },
+            ConfigParseError::UnknownProfileDotfileError(profile, dotfile) => {
+                write!(f, "Profile {} refers to dotfile {}, which is not in the config", profile, dotfile)
+            },
+            ConfigParseError::InvalidModeError(mode) => {
+                write!(f, "Mode {} is not valid, expected \"copy\", \"symlink\", \"hardlink\" or \"reflink\"", mode)
+            },
+            ConfigParseError::InvalidSpecialFilesError(special_files) => {
+                write!(f, "special_files {} is not valid, expected \"skip\" or \"fail\"", special_files)
+            },
         }
     }
 }
-
-impl From<std::io::Error> for ConfigParseError {
-    fn from(error: std::io::Error) -> ConfigParseError {
-        ConfigParseError::FileReadError(error)
-    }
-}
-
-impl From<std::string::FromUtf8Error> for ConfigParseError {
-    fn from(error: std::string::FromUtf8Error) -> ConfigParseError {
-        ConfigParseError::FromUtfError(error)
-    }
-}
-
-impl From<std::env::VarError> for ConfigParseError {
-    fn from(error: std::env::VarError) -> ConfigParseError {
-        ConfigParseError::ConfigEnvVariableError(error) 
-    }
-
-}
-
-impl From<toml::de::Error> for ConfigParseError {
-    fn from(error: toml::de::Error) -> ConfigParseError {
-        ConfigParseError::TomlParseError(error)
-    }
-}
-
-impl From<dot::DotfileError> for ConfigParseError {
-    fn from(error: dot::DotfileError) -> ConfigParseError {
-        ConfigParseError::DotfilesCreateError(error)
-    }
-}
@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::env;
+
+
+
+
+// Expands `~`, `$VAR`, `${VAR}` and `${VAR:-default}`, returning the name of the first variable that isn't set
+pub fn expand(value: &str) -> Result<String, String> {
+
+    let value = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", lookup("HOME")?, rest),
+        _ => String::from(value),
+    };
+
+    let mut expanded = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue
+        }
+
+        match chars.peek() {
+            Some('$') => {
+                chars.next();
+                expanded.push('$');
+            },
+            Some('{') => {
+                chars.next();
+                expanded.push_str(&expand_braced(&mut chars)?);
+            },
+            Some(next) if next.is_ascii_alphabetic() || *next == '_' => {
+                let mut name = String::new();
+                while let Some(next) = chars.next_if(|next| next.is_ascii_alphanumeric() || *next == '_') {
+                    name.push(next);
+                }
+                expanded.push_str(&lookup(&name)?);
+            },
+            _ => expanded.push('$'),
+        }
+    }
+
+    Ok(expanded)
+}
+
+
+// Expands a path, relative paths are taken to be relative to $HOME
+pub fn expand_path(value: &str) -> Result<PathBuf, String> {
+
+    let path = PathBuf::from(expand(value)?);
+
+    if path.is_relative() {
+        return Ok(PathBuf::from(lookup("HOME")?).join(path))
+    }
+
+    Ok(path)
+}
+
+
+fn expand_braced(chars: &mut Peekable<Chars>) -> Result<String, String> {
+
+    let mut name = String::new();
+    let mut default: Option<String> = None;
+    let mut depth = 0;
+
+    for c in chars.by_ref() {
+        match (c, &mut default) {
+            ('}', _) if depth == 0 => break,
+            ('{', Some(default)) => {
+                depth += 1;
+                default.push(c);
+            },
+            ('}', Some(default)) => {
+                depth -= 1;
+                default.push(c);
+            },
+            (c, Some(default)) => default.push(c),
+            (':', None) => default = Some(String::new()),
+            (c, None) => name.push(c),
+        }
+    }
+
+    match default {
+        Some(default) => {
+            let default = default.strip_prefix('-').unwrap_or(&default);
+            match env::var(&name) {
+                Ok(value) if !value.is_empty() => Ok(value),
+                _ => expand(default),
+            }
+        },
+        None => lookup(&name),
+    }
+}
+
+
+fn lookup(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| String::from(name))
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests run in parallel, so every test sets variables under its own names
+
+
+    #[test]
+    fn variables() {
+
+        env::set_var("EXPAND_TEST_NAME", "nvim");
+
+        assert_eq!(expand("$EXPAND_TEST_NAME/init.lua").unwrap(), "nvim/init.lua");
+        assert_eq!(expand("${EXPAND_TEST_NAME}rc").unwrap(), "nvimrc");
+        assert_eq!(expand("cost: $5 and $").unwrap(), "cost: $5 and $");
+    }
+
+
+    #[test]
+    fn defaults() {
+
+        env::set_var("EXPAND_TEST_SET", "set");
+        env::set_var("EXPAND_TEST_EMPTY", "");
+
+        assert_eq!(expand("${EXPAND_TEST_SET:-default}").unwrap(), "set");
+        assert_eq!(expand("${EXPAND_TEST_UNSET_1:-default}").unwrap(), "default");
+        assert_eq!(expand("${EXPAND_TEST_EMPTY:-default}").unwrap(), "default");
+        assert_eq!(expand("${EXPAND_TEST_UNSET_1:-}").unwrap(), "");
+    }
+
+
+    #[test]
+    fn nested_defaults() {
+
+        env::set_var("EXPAND_TEST_FALLBACK", "fallback");
+
+        assert_eq!(expand("${EXPAND_TEST_UNSET_2:-${EXPAND_TEST_FALLBACK}/x}").unwrap(), "fallback/x");
+        assert_eq!(expand("${EXPAND_TEST_UNSET_2:-${EXPAND_TEST_UNSET_3:-{last}}}").unwrap(), "{last}");
+        assert_eq!(expand("${EXPAND_TEST_UNSET_2:-${EXPAND_TEST_UNSET_3}}"), Err(String::from("EXPAND_TEST_UNSET_3")));
+    }
+
+
+    #[test]
+    fn unset_variables() {
+
+        assert_eq!(expand("$EXPAND_TEST_UNSET_4/config"), Err(String::from("EXPAND_TEST_UNSET_4")));
+        assert_eq!(expand("${EXPAND_TEST_UNSET_4}"), Err(String::from("EXPAND_TEST_UNSET_4")));
+    }
+
+
+    #[test]
+    fn escaped_dollar() {
+
+        env::set_var("EXPAND_TEST_ESCAPED", "value");
+
+        assert_eq!(expand("$$EXPAND_TEST_ESCAPED").unwrap(), "$EXPAND_TEST_ESCAPED");
+        assert_eq!(expand("a$$$EXPAND_TEST_ESCAPED").unwrap(), "a$value");
+    }
+
+
+    #[test]
+    fn home() {
+
+        let home = env::var("HOME").unwrap();
+
+        assert_eq!(expand("~").unwrap(), home);
+        assert_eq!(expand("~/.config").unwrap(), format!("{}/.config", home));
+        assert_eq!(expand("~user/.config").unwrap(), "~user/.config");
+        assert_eq!(expand("a/~/b").unwrap(), "a/~/b");
+    }
+
+
+    #[test]
+    fn relative_paths() {
+
+        let home = PathBuf::from(env::var("HOME").unwrap());
+
+        env::set_var("EXPAND_TEST_DIR", "dir");
+
+        assert_eq!(expand_path(".zshrc").unwrap(), home.join(".zshrc"));
+        assert_eq!(expand_path("$EXPAND_TEST_DIR/config").unwrap(), home.join("dir/config"));
+        assert_eq!(expand_path("/etc/hosts").unwrap(), PathBuf::from("/etc/hosts"));
+        assert_eq!(expand_path("~/.zshrc").unwrap(), home.join(".zshrc"));
+    }
+}
@@ -1 +1,3 @@
 pub mod cfg;
+pub mod expand;
+pub mod profile;
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::env;
+
+use toml::Table;
+use toml_edit::{ImDocument, Item};
+
+use crate::config::cfg::{ConfigParseError, DotfileEntry};
+use crate::config::expand;
+use crate::template::vars;
+
+
+
+
+pub struct Profile {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub dotfiles: Vec<String>,
+    pub tags: Vec<String>,
+    pub system_paths: BTreeMap<String, PathBuf>,
+    pub variables: Table,
+}
+
+impl Profile {
+    pub fn parse(name: &str, profile: &toml::Value) -> Result<Profile, ConfigParseError> {
+
+        let invalid = || ConfigParseError::InvalidProfileError(String::from(name));
+
+        let profile_table = profile.as_table().ok_or_else(invalid)?;
+
+        let strings = |key: &str| -> Result<Vec<String>, ConfigParseError> {
+            match profile_table.get(key) {
+                Some(values) => values
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|value| value.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid),
+                None => Ok(Vec::new()),
+            }
+        };
+
+        let hosts = strings("hosts")?;
+        let dotfiles = strings("dotfiles")?;
+        let tags = strings("tags")?;
+
+        let system_paths = match profile_table.get("system_paths") {
+            Some(paths) => paths
+                .as_table()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|(dotfile, path)| {
+                    let system_path = expand::expand_path(path.as_str().ok_or_else(invalid)?)
+                        .map_err(|variable| ConfigParseError::UndefinedVariableError(variable, format!("profile {} dotfile {}", name, dotfile)))?;
+                    Ok((dotfile.clone(), system_path))
+                })
+                .collect::<Result<BTreeMap<_, _>, ConfigParseError>>()?,
+            None => BTreeMap::new(),
+        };
+
+        let variables = match profile_table.get("variables") {
+            Some(variables) => variables.as_table().ok_or_else(invalid)?.clone(),
+            None => Table::new(),
+        };
+
+        Ok(Profile { name: String::from(name), hosts, dotfiles, tags, system_paths, variables })
+    }
+
+
+    pub fn find(config: &Table, document: &ImDocument<String>, requested: Option<String>) -> Result<Option<Profile>, ConfigParseError> {
+
+        let requested = requested.or_else(|| env::var("DOTFILES_PROFILE").ok());
+
+        let profiles = match config.get("profiles") {
+            Some(profiles) => profiles.as_table().ok_or(ConfigParseError::InvalidProfileError(String::from("profiles")))?,
+            None => match requested {
+                Some(name) => return Err(ConfigParseError::ProfileNotFoundError(name)),
+                None => return Ok(None),
+            },
+        };
+
+        if let Some(name) = requested {
+            return match profiles.get(&name) {
+                Some(profile) => Ok(Some(Profile::parse(&name, profile)?)),
+                None => Err(ConfigParseError::ProfileNotFoundError(name)),
+            }
+        }
+
+        let hostname = vars::hostname();
+
+        // The table sorts profiles by name, the first matching one in the file has to win
+        let names: Vec<&str> = document
+            .get("profiles")
+            .and_then(Item::as_table_like)
+            .map(|profiles| profiles.iter().map(|(name, _)| name).collect())
+            .unwrap_or_default();
+
+        for name in names {
+            let Some(profile) = profiles.get(name) else { continue };
+            let profile = Profile::parse(name, profile)?;
+
+            if profile.hosts.contains(&hostname) || (profile.hosts.is_empty() && *name == hostname) {
+                return Ok(Some(profile))
+            }
+        }
+
+        Ok(None)
+    }
+
+
+    fn selects(&self, entry: &DotfileEntry) -> bool {
+
+        if self.dotfiles.is_empty() && self.tags.is_empty() {
+            return true
+        }
+
+        self.dotfiles.contains(&entry.options.name) || entry.options.tags.iter().any(|tag| self.tags.contains(tag))
+    }
+
+
+    pub fn check(&self, entries: &[Result<DotfileEntry, ConfigParseError>]) -> Result<(), ConfigParseError> {
+
+        let names: Vec<&str> = entries
+            .iter()
+            .filter_map(|entry| entry.as_ref().ok())
+            .map(|entry| entry.options.name.as_str())
+            .collect();
+
+        let unknown = self.dotfiles
+            .iter()
+            .chain(self.system_paths.keys())
+            .find(|name| !names.contains(&name.as_str()));
+
+        match unknown {
+            Some(name) => Err(ConfigParseError::UnknownProfileDotfileError(self.name.clone(), name.clone())),
+            None => Ok(()),
+        }
+    }
+
+
+    pub fn apply(&self, entry: &DotfileEntry) -> Option<DotfileEntry> {
+
+        if !self.selects(entry) {
+            return None
+        }
+
+        Some(self.relocate(entry.clone()))
+    }
+
+
+    pub fn relocate(&self, mut entry: DotfileEntry) -> DotfileEntry {
+
+        if let Some(system_path) = self.system_paths.get(&entry.options.name) {
+            entry.system_path = system_path.clone();
+        }
+
+        entry
+    }
+}
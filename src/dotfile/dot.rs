@@ -1,12 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::error::Error;
-use std::env;
 use std::fs;
 use std::fmt;
 use std::io;
 
+use serde::Deserialize;
+
 use crate::fs::dir;
 use crate::fs::file;
+use crate::fs::ignore::{self, Ignore, IgnoreError};
+use crate::sync::backup::Snapshot;
+use crate::sync::state::State;
+use crate::sync::plan::{self, Operation};
+use crate::template::render::{self, TemplateError};
+use crate::template::vars::Variables;
 
 
 
@@ -16,18 +23,61 @@ pub enum Dotfile {
     Dir(dir::Directory)
 }
 
+impl Dotfile {
+    pub fn path(&self) -> &Path {
+        match self {
+            Dotfile::File(file) => &file.path,
+            Dotfile::Dir(dir) => &dir.path,
+        }
+    }
+}
+
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMode {
+    #[default]
+    Copy,
+    Symlink,
+    Hardlink,
+    Reflink,
+}
+
+impl DeployMode {
+    pub fn copy_strategy(&self) -> file::CopyStrategy {
+        match self {
+            DeployMode::Hardlink => file::CopyStrategy::Hardlink,
+            DeployMode::Reflink => file::CopyStrategy::Reflink,
+            DeployMode::Copy | DeployMode::Symlink => file::CopyStrategy::Copy,
+        }
+    }
+}
+
+
+#[derive(Default, Clone)]
+pub struct DotfileOptions {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub mode: DeployMode,
+    pub template: bool,
+    pub global_ignore: Vec<String>,
+    pub ignore: Vec<String>,
+    pub mode_bits: Option<u32>,
+    pub mirror: bool,
+    pub follow_symlinks: bool,
+    pub special_files: dir::SpecialFiles,
+}
+
 
 pub struct ManagedDotfile {
     pub manager_dotfile: Dotfile,
     pub system_dotfile: Dotfile,
+    pub options: DotfileOptions,
 }
 
 impl ManagedDotfile {
-    pub fn new(rel_git_location: PathBuf, sys_location: PathBuf) -> Result<Self, DotfileError> {
+    pub fn new(manager_dir: &Path, rel_git_location: PathBuf, sys_location: PathBuf, options: DotfileOptions) -> Result<Self, DotfileError> {
 
-        let home_dir = PathBuf::from(env::var("HOME")?);
-        let manager_dir = home_dir.join(PathBuf::from(".dotfiles/"));
-        
         let manager_path = manager_dir.join(rel_git_location);
         let system_path  = sys_location;
 
@@ -43,29 +93,47 @@ impl ManagedDotfile {
         };
 
         let manager_dotfile = if is_dir {
-            Dotfile::Dir(dir::Directory::new(&manager_path)?)
+            Dotfile::Dir(dir::Directory::new(&manager_path, &ManagedDotfile::ignore(manager_dir, &manager_path, &options)?, options.follow_symlinks, options.special_files)?)
         } else {
             Dotfile::File(file::File::new(&manager_path)?)
         };
 
         let system_dotfile = if is_dir {
-            Dotfile::Dir(dir::Directory::new(&system_path)?)
+            Dotfile::Dir(dir::Directory::new(&system_path, &ManagedDotfile::ignore(manager_dir, &system_path, &options)?, options.follow_symlinks, options.special_files)?)
         } else {
             Dotfile::File(file::File::new(&system_path)?)
         };
 
 
-        Ok(Self { manager_dotfile, system_dotfile })
+        Ok(Self { manager_dotfile, system_dotfile, options })
     }
 
 
-    fn check_is_dir(path: &PathBuf) -> Result<Option<bool>, DotfileError> {
+    fn ignore(manager_dir: &Path, root: &Path, options: &DotfileOptions) -> Result<Ignore, DotfileError> {
+
+        // Later patterns win, the global list comes first, then the ignore file and the dotfile's own list
+        let patterns: Vec<String> = options.global_ignore
+            .iter()
+            .cloned()
+            .chain(ignore::read_ignore_file(manager_dir)?)
+            .chain(options.ignore.iter().cloned())
+            .collect();
+
+        if patterns.is_empty() {
+            return Ok(Ignore::none())
+        }
+
+        Ok(Ignore::new(root, &patterns)?)
+    }
+
 
-        let path_is_dir = match fs::metadata(&path) {
+    fn check_is_dir(path: &Path) -> Result<Option<bool>, DotfileError> {
+
+        let path_is_dir = match fs::metadata(path) {
             Ok(data) if data.is_dir() => Some(true),
             Ok(_) => Some(false),
             Err(e) if e.kind() == io::ErrorKind::NotFound => None,
-            Err(e) => return Err(DotfileError::from(e)),
+            Err(e) => return Err(DotfileError::DotfileReadError(path.to_path_buf(), e)),
         };
 
         Ok(path_is_dir)
@@ -89,39 +157,258 @@ impl ManagedDotfile {
 
         let mut errors = Vec::new();
 
-        match manager_errors {
-            Some(e) => e.for_each(|error| errors.push(error)),
-            None => (),
+        if let Some(e) = manager_errors {
+            e.for_each(|error| errors.push(error));
         }
 
-        match system_errors {
-            Some(e) => e.for_each(|error| errors.push(error)),
-            None => (),
+        if let Some(e) = system_errors {
+            e.for_each(|error| errors.push(error));
         }
 
         errors
     }
 
 
-    pub fn copy_dotfile(&self, to_sys: bool) -> Result<Vec<dir::DirError>, DotfileError> {
+    pub fn get_skipped(&self) -> Vec<&dir::Skipped> {
+
+        [&self.manager_dotfile, &self.system_dotfile]
+            .into_iter()
+            .filter_map(|dotfile| match dotfile {
+                Dotfile::Dir(dir) => Some(dir.skipped.iter()),
+                Dotfile::File(_) => None,
+            })
+            .flatten()
+            .collect()
+    }
+
 
-        let (current, destination) = if to_sys {
+    fn get_direction(&self, to_sys: bool) -> (&Dotfile, &Dotfile) {
+
+        if to_sys {
             (&self.manager_dotfile, &self.system_dotfile)
         } else {
             (&self.system_dotfile, &self.manager_dotfile)
-        };
+        }
+    }
+
+
+    pub fn copy_dotfile(&self, to_sys: bool, backup: &Snapshot, state: &mut State, variables: &Variables, force: bool) -> Result<plan::ApplyReport, DotfileError> {
+
+        let operations = self.plan_copy(to_sys, state, variables, force)?;
+
+        let plan = plan::Plan { operations, errors: Vec::new(), to_sys };
+
+        Ok(plan::apply(&plan, backup, state))
+    }
+
+
+    pub fn is_linked(&self) -> bool {
+        fs::read_link(self.system_dotfile.path()).is_ok_and(|target| target == self.manager_dotfile.path())
+    }
+
+
+    pub fn plan_copy(&self, to_sys: bool, state: &State, variables: &Variables, force: bool) -> Result<Vec<Operation>, DotfileError> {
+
+        // Templates are only rendered onto the system, collecting would overwrite them with the output
+        if self.options.template && !to_sys {
+            return Ok(Vec::new())
+        }
+
+        if self.options.mode == DeployMode::Symlink && !self.options.template {
+            if to_sys {
+                let changes = self.plan_link()?;
+                if !force {
+                    ManagedDotfile::check_conflicts(&changes, to_sys, state)?;
+                }
+                return Ok(changes)
+            } else if self.is_linked() {
+                return Ok(Vec::new())
+            }
+        }
+
+        let (current, destination) = self.get_direction(to_sys);
 
+        let changes = if let (Dotfile::File(current_file), Dotfile::File(dest_file)) = (current, destination) {
+            let mut changes = Vec::new();
 
-        let copy_results = if let (Dotfile::File(current_file), Dotfile::File(dest_file)) = (current, destination) {
-            current_file.copy(&dest_file.path)?;
-            Vec::new()
+            if let Some(parent_dir) = dest_file.path.parent() {
+                if !parent_dir.exists() {
+                    changes.push(Operation::Mkdir { path: parent_dir.to_path_buf() });
+                }
+            }
+
+            changes.push(current_file.plan_copy(&dest_file.path, self.options.mode.copy_strategy())?);
+            changes
         } else if let (Dotfile::Dir(current_dir), Dotfile::Dir(dest_dir)) = (current, destination) {
-            current_dir.copy(&dest_dir.path)?
+            let mirror = self.options.mirror.then_some(dest_dir);
+            current_dir.plan_copy(&dest_dir.path, self.options.mode.copy_strategy(), mirror)?
+        } else {
+            return Err(DotfileError::DotfileCopyError(current.path().to_path_buf(), destination.path().to_path_buf()))
+        };
+
+        let changes = if self.options.template {
+            changes
+                .into_iter()
+                .map(|operation| ManagedDotfile::plan_render(operation, variables))
+                .collect::<Result<Vec<_>, _>>()?
         } else {
-            return Err(DotfileError::DotfileCopyError)
+            changes
+        };
+
+        let changes = match self.options.mode_bits {
+            Some(mode_bits) if to_sys => ManagedDotfile::plan_mode_bits(changes, mode_bits)?,
+            _ => changes,
+        };
+
+        if !force {
+            ManagedDotfile::check_conflicts(&changes, to_sys, state)?;
+        }
+
+        Ok(changes)
+    }
+
+
+    fn plan_render(operation: Operation, variables: &Variables) -> Result<Operation, DotfileError> {
+
+        let Some((source, destination)) = operation.file_paths() else {
+            return Ok(operation)
+        };
+
+        let (source, destination) = (source.to_path_buf(), destination.to_path_buf());
+
+        let contents = ManagedDotfile::render(&source, variables)?;
+
+        let operation = match fs::read(&destination) {
+            Ok(current) if current == contents.as_bytes() => Operation::Skip { source, destination },
+            Ok(_) => Operation::Render { source, destination, contents, replace: true },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Operation::Render { source, destination, contents, replace: false },
+            Err(e) => return Err(DotfileError::DotfileReadError(destination, e)),
+        };
+
+        Ok(operation)
+    }
+
+
+    fn plan_mode_bits(operations: Vec<Operation>, mode_bits: u32) -> Result<Vec<Operation>, DotfileError> {
+
+        let mut changes = Vec::new();
+
+        for operation in operations {
+            match operation {
+                Operation::Chmod { path, .. } if !path.is_dir() => {
+                    if file::mode(&path).map_err(|e| DotfileError::DotfileReadError(path.clone(), e))? != mode_bits {
+                        changes.push(Operation::Chmod { path, mode: mode_bits });
+                    }
+                },
+                Operation::Skip { source, destination } => {
+                    let drifted = file::mode(&destination).map_err(|e| DotfileError::DotfileReadError(destination.clone(), e))? != mode_bits;
+                    changes.push(Operation::Skip { source, destination: destination.clone() });
+                    if drifted {
+                        changes.push(Operation::Chmod { path: destination, mode: mode_bits });
+                    }
+                },
+                operation => {
+                    let destination = operation.file_paths().map(|(_, destination)| destination.to_path_buf());
+                    changes.push(operation);
+                    if let Some(path) = destination {
+                        changes.push(Operation::Chmod { path, mode: mode_bits });
+                    }
+                },
+            }
+        }
+
+        Ok(changes)
+    }
+
+
+    pub fn render(template: &Path, variables: &Variables) -> Result<String, DotfileError> {
+
+        let template_text = fs::read_to_string(template).map_err(|e| DotfileError::DotfileReadError(template.to_path_buf(), e))?;
+
+        render::render(&template_text, variables)
+            .map_err(|error| DotfileError::TemplateRenderError(template.to_path_buf(), error))
+    }
+
+
+    fn plan_link(&self) -> Result<Vec<Operation>, DotfileError> {
+
+        let link = self.system_dotfile.path().to_path_buf();
+        let target = self.manager_dotfile.path().to_path_buf();
+
+        if !target.exists() {
+            return Err(DotfileError::LinkTargetMissingError(target))
+        }
+
+        let source = target.clone();
+
+        let mut changes = Vec::new();
+
+        match fs::symlink_metadata(&link) {
+            Ok(_) if self.is_linked() => (),
+            Ok(_) => changes.push(Operation::Symlink { source, target, link, replace: true }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(parent_dir) = link.parent() {
+                    if !parent_dir.exists() {
+                        changes.push(Operation::Mkdir { path: parent_dir.to_path_buf() });
+                    }
+                }
+
+                changes.push(Operation::Symlink { source, target, link, replace: false });
+            },
+            Err(e) => return Err(DotfileError::DotfileReadError(link, e)),
         };
 
-        Ok(copy_results)
+        Ok(changes)
+    }
+
+
+    fn check_conflicts(operations: &[Operation], to_sys: bool, state: &State) -> Result<(), DotfileError> {
+
+        let mut conflicts = Vec::new();
+
+        for operation in operations.iter() {
+            let (Operation::Overwrite { source, destination, .. }
+                | Operation::Render { source, destination, replace: true, .. }
+                | Operation::Symlink { source, link: destination, replace: true, .. }
+                | Operation::Delete { source, destination }) = operation else {
+                continue
+            };
+
+            let system_path = if to_sys { destination } else { source };
+
+            // Deleted directories are checked through the files recorded under them
+            if destination.is_dir() {
+                for (recorded_path, synced_hash) in state.recorded_under(system_path) {
+                    let Ok(relative_path) = recorded_path.strip_prefix(system_path) else {
+                        continue
+                    };
+
+                    let current_path = destination.join(relative_path);
+
+                    if current_path.is_file() && file::hash_file(&current_path).map_err(|e| DotfileError::DotfileReadError(current_path.clone(), e))? != synced_hash {
+                        conflicts.push(current_path);
+                    }
+                }
+                continue
+            }
+
+            // A link being replaced may point nowhere, there is nothing to hash then
+            if !destination.is_file() {
+                continue
+            }
+
+            if let Some(synced_hash) = state.get(system_path) {
+                if file::hash_file(destination).map_err(|e| DotfileError::DotfileReadError(destination.clone(), e))? != synced_hash {
+                    conflicts.push(destination.clone());
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(DotfileError::Conflict(conflicts))
+        }
+
+        Ok(())
     }
 }
 
@@ -130,24 +417,34 @@ impl ManagedDotfile {
 
 #[derive(Debug)]
 pub enum DotfileError {
-    DotfileIOError(std::io::Error),
-    DotfileEnvError(std::env::VarError),
+    DotfileReadError(PathBuf, std::io::Error),
     FileCopyError(file::FileError),
     DirectoryCopyError(dir::DirError),
-    FilesDontExistError,
-    DotfileCopyError,
+    DotfileCopyError(PathBuf, PathBuf),
+    LinkTargetMissingError(PathBuf),
+    TemplateRenderError(PathBuf, TemplateError),
+    IgnoreError(IgnoreError),
+    Conflict(Vec<PathBuf>),
 }
 
-impl Error for DotfileError {}
+impl Error for DotfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DotfileError::DotfileReadError(_, io_error) => Some(io_error),
+            DotfileError::FileCopyError(copy_error) => copy_error.source(),
+            DotfileError::DirectoryCopyError(copy_error) => copy_error.source(),
+            DotfileError::TemplateRenderError(_, template_error) => Some(template_error),
+            DotfileError::IgnoreError(ignore_error) => ignore_error.source(),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for DotfileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DotfileError::DotfileIOError(io_error) => {
-                write!(f, "{}", io_error)
-            },
-            DotfileError::DotfileEnvError(env_error) => {
-                write!(f, "{}", env_error)
+            DotfileError::DotfileReadError(path, _) => {
+                write!(f, "Failed to read {}", path.display())
             },
             DotfileError::FileCopyError(copy_error) => {
                 write!(f, "{}", copy_error)
@@ -155,34 +452,41 @@ impl fmt::Display for DotfileError {
             DotfileError::DirectoryCopyError(copy_error) => {
                 write!(f, "{}", copy_error)
             },
-            DotfileError::FilesDontExistError => {
-                write!(f, "Neither file exists")
+            DotfileError::DotfileCopyError(source, destination) => {
+                write!(f, "Cannot copy {} to {}, one is a file and the other a directory", source.display(), destination.display())
+            },
+            DotfileError::LinkTargetMissingError(target) => {
+                write!(f, "Cannot link to {}, it does not exist in the manager folder", target.display())
             },
-            DotfileError::DotfileCopyError => {
-                write!(f, "Failed to copy dotfile")
+            DotfileError::TemplateRenderError(template, _) => {
+                write!(f, "Failed to render template {}", template.display())
+            },
+            DotfileError::IgnoreError(ignore_error) => {
+                write!(f, "{}", ignore_error)
+            },
+            DotfileError::Conflict(paths) => {
+                write!(f, "Changed since the last sync, use --force to overwrite:")?;
+                for path in paths.iter() {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
             },
         }
     }
 }
 
-impl From<std::io::Error> for DotfileError {
-    fn from(error: std::io::Error) -> DotfileError {
-        DotfileError::DotfileIOError(error)
-    }
-}
-
-impl From<std::env::VarError> for DotfileError {
-    fn from(error: std::env::VarError) -> DotfileError {
-        DotfileError::DotfileEnvError(error)
-    }
-}
-
 impl From<file::FileError> for DotfileError {
     fn from(error: file::FileError) -> DotfileError {
         DotfileError::FileCopyError(error)
     }
 }
 
+impl From<IgnoreError> for DotfileError {
+    fn from(error: IgnoreError) -> DotfileError {
+        DotfileError::IgnoreError(error)
+    }
+}
+
 impl From<dir::DirError> for DotfileError {
     fn from(error: dir::DirError) -> DotfileError {
         DotfileError::DirectoryCopyError(error)
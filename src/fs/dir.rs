@@ -1,134 +1,369 @@
-use std::fs::{self, DirEntry};
-use std::path::PathBuf;
+use std::fs::{self, DirEntry, FileType};
+use std::path::{Path, PathBuf};
 use std::fmt;
 use std::error::Error;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 
-use crate::fs::file::{self, File};
+use filetime::FileTime;
+use serde::Deserialize;
+
+use crate::fs::file::{self, CopyStrategy, File};
+use crate::fs::ignore::Ignore;
+use crate::fs::link::Link;
+use crate::sync::plan::Operation;
 
 
 
 
 pub struct Directory {
-    files: Vec<File>,
-    directories: Vec<Directory>,
+    pub files: Vec<File>,
+    pub directories: Vec<Directory>,
+    pub links: Vec<Link>,
+    pub skipped: Vec<Skipped>,
     pub path: PathBuf,
     pub errors: Vec<DirError>,
 }
 
+
+enum EntryKind {
+    File,
+    Dir,
+    Link,
+    Special(SpecialKind),
+}
+
 impl Directory {
-    pub fn new(path: &PathBuf) -> Result<Directory, DirError> {
+    pub fn new(path: &Path, ignore: &Ignore, follow_symlinks: bool, special_files: SpecialFiles) -> Result<Directory, DirError> {
+        Directory::scan(path, ignore, follow_symlinks, special_files, &[])
+    }
+
+
+    // Ancestors are the resolved paths of the directories above, used to stop at followed links that loop
+    fn scan(path: &Path, ignore: &Ignore, follow_symlinks: bool, special_files: SpecialFiles, ancestors: &[PathBuf]) -> Result<Directory, DirError> {
 
         if !path.exists() {
-            fs::create_dir_all(path)?;
+            return Ok(Directory{ files: Vec::new(), directories: Vec::new(), links: Vec::new(), skipped: Vec::new(), path: path.to_path_buf(), errors: Vec::new() })
         }
 
-        let entries = fs::read_dir(path)?;
+        let mut ancestors = ancestors.to_vec();
+        if follow_symlinks {
+            ancestors.push(fs::canonicalize(path).map_err(|e| DirError::EntryReadError(path.to_path_buf(), e))?);
+        }
+
+        let entries = fs::read_dir(path).map_err(|e| DirError::DirReadError(path.to_path_buf(), e))?;
         let (valid_entries, io_errors): (Vec<_>, Vec<_>) = entries.partition(|entry| entry.is_ok());
-        let valid_entries: Vec<DirEntry> = valid_entries.into_iter().map(|entry| entry.unwrap()).collect();
+        let valid_entries: Vec<DirEntry> = valid_entries
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| !ignore.is_ignored(&entry.path(), entry.file_type().is_ok_and(|file_type| file_type.is_dir())))
+            .collect();
 
 
-        let dirs = Directory::get_dirs(&valid_entries);
+        let dirs = Directory::get_dirs(&valid_entries, ignore, follow_symlinks, special_files, &ancestors);
         let (valid_dirs, dir_errors): (Vec<_>, Vec<_>) = dirs.into_iter().partition(|dir| dir.is_ok());
-        let directories: Vec<Directory> = valid_dirs.into_iter().map(|dir| dir.unwrap()).collect();
+        let mut directories: Vec<Directory> = valid_dirs.into_iter().map(|dir| dir.unwrap()).collect();
 
 
-        let files = Directory::get_files(&valid_entries);
+        let files = Directory::get_files(&valid_entries, follow_symlinks);
         let (valid_files, file_errors): (Vec<_>, Vec<_>) = files.into_iter().partition(|file| file.is_ok());
         let files: Vec<File> = valid_files.into_iter().map(|file| file.unwrap()).collect();
 
 
-        let dir_errors = dir_errors.into_iter().map(|err| DirError::from(err.err().unwrap()));
-        let io_errors = io_errors.into_iter().map(|err| DirError::from(err.err().unwrap()));
-        let file_errors = file_errors.into_iter().map(|err| DirError::from(err.err().unwrap()));
+        let links = Directory::get_links(&valid_entries, follow_symlinks);
+        let (valid_links, link_errors): (Vec<_>, Vec<_>) = links.into_iter().partition(|link| link.is_ok());
+        let links: Vec<Link> = valid_links.into_iter().map(|link| link.unwrap()).collect();
+
+
+        let skipped = Directory::get_skipped(&valid_entries, follow_symlinks, special_files);
+        let (valid_skipped, special_errors): (Vec<_>, Vec<_>) = skipped.into_iter().partition(|skipped| skipped.is_ok());
+        let mut skipped: Vec<Skipped> = valid_skipped.into_iter().map(|skipped| skipped.unwrap()).collect();
+
+
+        let dir_errors = dir_errors.into_iter().map(|err| err.err().unwrap());
+        let io_errors = io_errors.into_iter().map(|err| DirError::DirReadError(path.to_path_buf(), err.err().unwrap()));
+        let file_errors = file_errors.into_iter().map(|err| err.err().unwrap());
+        let link_errors = link_errors.into_iter().map(|err| err.err().unwrap());
+        let special_errors = special_errors.into_iter().map(|err| err.err().unwrap());
+
+        // Errors and skipped entries from subdirectories are reported with the top directory
+        let nested_errors: Vec<DirError> = directories.iter_mut().flat_map(|dir| dir.errors.drain(..)).collect();
+        skipped.extend(directories.iter_mut().flat_map(|dir| dir.skipped.drain(..)));
+
+        let errors: Vec<DirError> = io_errors
+            .chain(dir_errors)
+            .chain(file_errors)
+            .chain(link_errors)
+            .chain(special_errors)
+            .chain(nested_errors)
+            .collect();
 
-        let errors: Vec<DirError> = io_errors.chain(dir_errors).chain(file_errors).collect();
+        Ok(Directory{ files, directories, links, skipped, path: path.to_path_buf(), errors })
+    }
+
+
+    // Symlinks are kept as links unless they are followed, links that point nowhere are always kept
+    fn entry_kind(entry: &DirEntry, follow_symlinks: bool) -> Result<Option<EntryKind>, DirError> {
+
+        let file_type = entry.file_type().map_err(|e| DirError::EntryReadError(entry.path(), e))?;
+
+        if !file_type.is_symlink() {
+            return Ok(Directory::kind_of(file_type))
+        }
+
+        if !follow_symlinks {
+            return Ok(Some(EntryKind::Link))
+        }
 
-        Ok(Directory{ files, directories, path: path.clone(), errors })
+        match fs::metadata(entry.path()) {
+            Ok(data) => Ok(Directory::kind_of(data.file_type())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some(EntryKind::Link)),
+            Err(e) => Err(DirError::EntryReadError(entry.path(), e)),
+        }
     }
 
 
-    fn get_files(entries: &Vec<DirEntry>) -> Vec<Result<File, DirError>> {
+    fn kind_of(file_type: FileType) -> Option<EntryKind> {
+
+        if file_type.is_file() {
+            Some(EntryKind::File)
+        } else if file_type.is_dir() {
+            Some(EntryKind::Dir)
+        } else if file_type.is_symlink() {
+            Some(EntryKind::Link)
+        } else if file_type.is_socket() {
+            Some(EntryKind::Special(SpecialKind::Socket))
+        } else if file_type.is_fifo() {
+            Some(EntryKind::Special(SpecialKind::Fifo))
+        } else if file_type.is_block_device() {
+            Some(EntryKind::Special(SpecialKind::BlockDevice))
+        } else if file_type.is_char_device() {
+            Some(EntryKind::Special(SpecialKind::CharDevice))
+        } else {
+            None
+        }
+    }
+
 
-        let files: Vec<_> = entries.into_iter().filter_map(|entry| match entry.metadata() {
-            Ok(data) if data.is_file() => {
+    fn get_files(entries: &[DirEntry], follow_symlinks: bool) -> Vec<Result<File, DirError>> {
+
+        let files: Vec<_> = entries.iter().filter_map(|entry| match Directory::entry_kind(entry, follow_symlinks) {
+            Ok(Some(EntryKind::File)) => {
                 match File::new(&entry.path()) {
                     Ok(file) => Some(Ok(file)),
                     Err(e) => Some(Err(DirError::from(e))),
                 }
             },
             Ok(_) => None,
-            Err(e) => Some(Err(DirError::from(e))),
+            Err(e) => Some(Err(e)),
         }).collect();
 
         files
     }
 
 
-    fn get_dirs(entries: &Vec<DirEntry>) -> Vec<Result<Directory, DirError>> {
+    fn get_dirs(entries: &[DirEntry], ignore: &Ignore, follow_symlinks: bool, special_files: SpecialFiles, ancestors: &[PathBuf]) -> Vec<Result<Directory, DirError>> {
 
-        let directories: Vec<_> = entries.into_iter().filter_map(|entry| match entry.metadata() {
-            Ok(data) if data.is_dir() => {
-                match Directory::new(&entry.path()) {
-                    Ok(dir) => Some(Ok(dir)),
-                    Err(e) => Some(Err(DirError::from(e))),
+        let directories: Vec<_> = entries.iter().filter_map(|entry| match Directory::entry_kind(entry, follow_symlinks) {
+            Ok(Some(EntryKind::Dir)) => {
+                match Directory::check_loop(&entry.path(), ancestors) {
+                    Ok(()) => Some(Directory::scan(&entry.path(), ignore, follow_symlinks, special_files, ancestors)),
+                    Err(e) => Some(Err(e)),
                 }
             },
             Ok(_) => None,
-            Err(e) => Some(Err(DirError::from(e))),
+            Err(e) => Some(Err(e)),
         }).collect();
 
         directories
     }
 
 
-    pub fn copy(&self, dest_path: &PathBuf) -> Result<Vec<DirError>, DirError> {
+    fn get_links(entries: &[DirEntry], follow_symlinks: bool) -> Vec<Result<Link, DirError>> {
 
-        let file_copy_results: Vec<_> = self.files
-            .iter()
-            .map(|file| {
-                file.copy( &dest_path.join( PathBuf::from(&file.filename) ) )
+        let links: Vec<_> = entries.iter().filter_map(|entry| match Directory::entry_kind(entry, follow_symlinks) {
+            Ok(Some(EntryKind::Link)) => {
+                match Link::new(&entry.path()) {
+                    Ok(link) => Some(Ok(link)),
+                    Err(e) => Some(Err(DirError::from(e))),
+                }
+            },
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
         }).collect();
 
+        links
+    }
 
-        let dir_copy_results = {
-            let dirs = self.directories.iter();
 
-            let results = dirs.map(|dir| {
-                let dir_name = match dir.path.file_name() {
-                    Some(filename) => filename,
-                    None => return Err(DirError::NoDirectoryNameError),
-                };
+    fn get_skipped(entries: &[DirEntry], follow_symlinks: bool, special_files: SpecialFiles) -> Vec<Result<Skipped, DirError>> {
 
-                let new_dest_path = dest_path.join(PathBuf::from(dir_name));
+        let skipped: Vec<_> = entries.iter().filter_map(|entry| match Directory::entry_kind(entry, follow_symlinks) {
+            Ok(Some(EntryKind::Special(kind))) => match special_files {
+                SpecialFiles::Skip => Some(Ok(Skipped { path: entry.path(), kind })),
+                SpecialFiles::Fail => Some(Err(DirError::SpecialFileError(entry.path(), kind))),
+            },
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }).collect();
+
+        skipped
+    }
 
-                if !new_dest_path.exists() {
-                    fs::create_dir(&new_dest_path)?;
-                }
 
-                dir.copy(&new_dest_path)
-            }).collect::<Vec<_>>();
+    // A followed link loops when it points at one of the directories it is inside of
+    fn check_loop(path: &Path, ancestors: &[PathBuf]) -> Result<(), DirError> {
 
-            results
-        };
+        let read_error = |e| DirError::EntryReadError(path.to_path_buf(), e);
 
-        let mut copy_errors = Vec::new();
+        if ancestors.is_empty() || !fs::symlink_metadata(path).map_err(read_error)?.is_symlink() {
+            return Ok(())
+        }
 
-        file_copy_results.into_iter().for_each(|result| if result.is_err() {
-            copy_errors.push(DirError::from(result.err().unwrap()))
-        });
+        let target = fs::canonicalize(path).map_err(read_error)?;
 
-        dir_copy_results.into_iter().for_each(|result| match result {
-            Err(e) => {
-                copy_errors.push(DirError::from(e));
-            },
-            Ok(copy_results) => {
-                copy_results.into_iter().for_each(|error| copy_errors.push(error));
+        if ancestors.iter().any(|ancestor| ancestor.starts_with(&target)) {
+            return Err(DirError::SymlinkLoopError(path.to_path_buf(), target))
+        }
+
+        Ok(())
+    }
+
+
+    // With the scanned destination, entries it has that this directory doesn't are deleted first
+    pub fn plan_copy(&self, dest_path: &Path, strategy: CopyStrategy, mirror: Option<&Directory>) -> Result<Vec<Operation>, DirError> {
+
+        let mut changes = Vec::new();
+
+        let dest_mode = file::mode(dest_path).ok();
+
+        if dest_mode.is_none() {
+            changes.push(Operation::Mkdir { path: dest_path.to_path_buf() });
+        }
+
+        if let Some(dest_dir) = mirror {
+            changes.extend(self.plan_deletes(dest_dir));
+        }
+
+        for file in self.files.iter() {
+            changes.push(file.plan_copy(&dest_path.join(&file.filename), strategy)?);
+        }
+
+        for link in self.links.iter() {
+            changes.extend(link.plan_copy(&dest_path.join(&link.filename))?);
+        }
+
+        for dir in self.directories.iter() {
+            let dir_name = match dir.path.file_name() {
+                Some(filename) => filename,
+                None => return Err(DirError::NoDirectoryNameError(dir.path.clone())),
+            };
+
+            let dir_path = dest_path.join(dir_name);
+            let dest_dir = mirror.and_then(|dest_dir| dest_dir.directories.iter().find(|dest_dir| dest_dir.path == dir_path));
+
+            changes.extend(dir.plan_copy(&dir_path, strategy, dest_dir)?);
+        }
+
+        // Set once the contents are written, a read-only mode would stop them being copied in
+        // and writing them would change the modification time
+        let metadata = fs::metadata(&self.path).map_err(|e| DirError::EntryReadError(self.path.clone(), e))?;
+        let mode = metadata.permissions().mode() & file::MODE_MASK;
+
+        if dest_mode != Some(mode) || changes.iter().any(Operation::is_change) {
+            let mtime = FileTime::from_last_modification_time(&metadata);
+            changes.push(Operation::SetMetadata { path: dest_path.to_path_buf(), mode, mtime });
+        }
+
+        // An existing read-only destination is opened up until its mode is set again
+        if let Some(dest_mode) = dest_mode.filter(|dest_mode| dest_mode & 0o700 != 0o700) {
+            if changes.iter().any(|operation| operation.is_change() && !matches!(operation, Operation::SetMetadata { .. })) {
+                changes.insert(0, Operation::Chmod { path: dest_path.to_path_buf(), mode: dest_mode | 0o700 });
+            }
+        }
+
+        Ok(changes)
+    }
+
+
+    // Deletes entries of the destination that are missing from this directory
+    fn plan_deletes(&self, dest_dir: &Directory) -> Vec<Operation> {
+
+        let mut changes = Vec::new();
+
+        for file in dest_dir.files.iter() {
+            let source_path = self.path.join(&file.filename);
+
+            if fs::symlink_metadata(&source_path).is_err() {
+                changes.push(Operation::Delete { source: source_path, destination: file.path.clone() });
             }
-        });
+        }
+
+        for link in dest_dir.links.iter() {
+            let source_path = self.path.join(&link.filename);
+
+            if fs::symlink_metadata(&source_path).is_err() {
+                changes.push(Operation::Delete { source: source_path, destination: link.path.clone() });
+            }
+        }
+
+        for dir in dest_dir.directories.iter() {
+            let Some(dir_name) = dir.path.file_name() else {
+                continue
+            };
 
+            let source_path = self.path.join(dir_name);
 
-        Ok(copy_errors)
+            if fs::symlink_metadata(&source_path).is_err() {
+                changes.push(Operation::Delete { source: source_path, destination: dir.path.clone() });
+            }
+        }
 
+        changes
+    }
+}
+
+
+
+
+// What to do with sockets, FIFOs and devices found while scanning, which can't be copied
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialFiles {
+    #[default]
+    Skip,
+    Fail,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpecialKind {
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+impl fmt::Display for SpecialKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecialKind::Socket => f.pad("socket"),
+            SpecialKind::Fifo => f.pad("fifo"),
+            SpecialKind::BlockDevice => f.pad("block device"),
+            SpecialKind::CharDevice => f.pad("character device"),
+        }
+    }
+}
+
+
+pub struct Skipped {
+    pub path: PathBuf,
+    pub kind: SpecialKind,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.kind)
     }
 }
 
@@ -137,45 +372,49 @@ impl Directory {
 
 #[derive(Debug)]
 pub enum DirError {
-    DirCopyMetadataError(std::env::VarError),
-    DirIOError(std::io::Error),
+    DirReadError(PathBuf, std::io::Error),
+    EntryReadError(PathBuf, std::io::Error),
     DirFileCopyError(file::FileError),
-    NoDirectoryNameError,
+    NoDirectoryNameError(PathBuf),
+    SymlinkLoopError(PathBuf, PathBuf),
+    SpecialFileError(PathBuf, SpecialKind),
 }
 
-impl Error for DirError {}
+impl Error for DirError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DirError::DirReadError(_, io_error) | DirError::EntryReadError(_, io_error) => Some(io_error),
+            DirError::DirFileCopyError(copy_error) => copy_error.source(),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for DirError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DirError::DirCopyMetadataError(var_error) => {
-                write!(f, "{}", var_error)
+            DirError::DirReadError(path, _) => {
+                write!(f, "Failed to read directory {}", path.display())
             },
-            DirError::DirIOError(io_error) => {
-                write!(f, "{}", io_error)
+            DirError::EntryReadError(path, _) => {
+                write!(f, "Failed to read {}", path.display())
             },
             DirError::DirFileCopyError(copy_error) => {
                 write!(f, "{}", copy_error)
             },
-            DirError::NoDirectoryNameError => {
-                write!(f, "Directory does not have a valid name")
-            }
+            DirError::NoDirectoryNameError(path) => {
+                write!(f, "Directory {} does not have a valid name", path.display())
+            },
+            DirError::SymlinkLoopError(link, target) => {
+                write!(f, "Not following {}, it links back to {} which contains it", link.display(), target.display())
+            },
+            DirError::SpecialFileError(path, kind) => {
+                write!(f, "Cannot copy {}, it is a {}", path.display(), kind)
+            },
         }
     }
 }
 
-impl From<std::env::VarError> for DirError {
-    fn from(error: std::env::VarError) -> DirError {
-       DirError::DirCopyMetadataError(error) 
-    }
-}
-
-impl From<std::io::Error> for DirError {
-    fn from(error: std::io::Error) -> DirError {
-        DirError::DirIOError(error)
-    }
-}
-
 impl From<file::FileError> for DirError {
     fn from(error: file::FileError) -> DirError {
         DirError::DirFileCopyError(error)
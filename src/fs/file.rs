@@ -1,9 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::error::Error;
 use std::fmt;
+use std::io;
 
+use filetime::FileTime;
+use sha2::{Digest, Sha256};
 
+use crate::sync::plan::Operation;
+
+
+
+
+pub const MODE_MASK: u32 = 0o7777;
 
 
 pub struct File {
@@ -12,36 +22,116 @@ pub struct File {
 }
 
 impl File {
-    pub fn new(path: &PathBuf) -> Result<File, FileError> {
+    pub fn new(path: &Path) -> Result<File, FileError> {
 
-        let parent_dir = match path.parent() {
-            Some(parent) => parent,
-            None => return Err(FileError::NoParentDirError),
-        };
-
-        if !parent_dir.exists() {
-            fs::create_dir_all(parent_dir)?;
+        if path.parent().is_none() {
+            return Err(FileError::NoParentDirError(path.to_path_buf()))
         }
 
         let filename = match path.file_name() {
             Some(filename) => match filename.to_str() {
                 Some(filename) => String::from(filename),
-                None => return Err(FileError::FilenameInvalidUTFError),
+                None => return Err(FileError::FilenameInvalidUTFError(path.to_path_buf())),
             },
-            None => return Err(FileError::NoFileNameError),
+            None => return Err(FileError::NoFileNameError(path.to_path_buf())),
         };
 
         Ok(File{ path: path.to_path_buf(), filename })
     }
 
 
-    pub fn copy(&self, dest_path: &PathBuf) -> Result<(), FileError> {
+    pub fn copy(&self, dest_path: &Path, strategy: CopyStrategy) -> Result<CopyStrategy, FileError> {
+
+        let linked = match strategy {
+            CopyStrategy::Hardlink => fs::hard_link(&self.path, dest_path).is_ok(),
+            CopyStrategy::Reflink => reflink_copy::reflink(&self.path, dest_path).is_ok(),
+            CopyStrategy::Copy => false,
+        };
+
+        let used = if linked {
+            strategy
+        } else {
+            fs::copy(&self.path, dest_path)
+                .map_err(|e| FileError::CopyError(self.path.clone(), dest_path.to_path_buf(), e))?;
+            CopyStrategy::Copy
+        };
+
+        // A hardlink already shares the source's mode and mtime
+        if used != CopyStrategy::Hardlink {
+            let metadata = fs::metadata(&self.path).map_err(|e| FileError::ReadError(self.path.clone(), e))?;
+
+            fs::set_permissions(dest_path, metadata.permissions())
+                .and_then(|_| filetime::set_file_mtime(dest_path, FileTime::from_last_modification_time(&metadata)))
+                .map_err(|e| FileError::MetadataCopyError(dest_path.to_path_buf(), e))?;
+        }
+
+        Ok(used)
+    }
+
+
+    pub fn plan_copy(&self, dest_path: &Path, strategy: CopyStrategy) -> Result<Operation, FileError> {
+
+        let source = self.path.clone();
+        let destination = dest_path.to_path_buf();
+
+        let read = |path: &Path| fs::read(path).map_err(|e| FileError::ReadError(path.to_path_buf(), e));
+        let read_mode = |path: &Path| mode(path).map_err(|e| FileError::ReadError(path.to_path_buf(), e));
+
+        let operation = if !dest_path.exists() {
+            Operation::Copy { source, destination, strategy }
+        } else if read(&self.path)? == read(dest_path)? {
+            let source_mode = read_mode(&self.path)?;
+
+            if read_mode(dest_path)? != source_mode {
+                Operation::Chmod { path: destination, mode: source_mode }
+            } else {
+                Operation::Skip { source, destination }
+            }
+        } else {
+            Operation::Overwrite { source, destination, strategy }
+        };
+
+        Ok(operation)
+    }
+
+}
+
 
-        fs::copy(&self.path, dest_path)?;
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CopyStrategy {
+    #[default]
+    Copy,
+    Hardlink,
+    Reflink,
+}
 
-        Ok(())
+impl fmt::Display for CopyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyStrategy::Copy => f.pad("copy"),
+            CopyStrategy::Hardlink => f.pad("hardlink"),
+            CopyStrategy::Reflink => f.pad("reflink"),
+        }
     }
+}
+
 
+pub fn mode(path: &Path) -> Result<u32, io::Error> {
+    Ok(fs::metadata(path)?.permissions().mode() & MODE_MASK)
+}
+
+
+pub fn hash(content: &[u8]) -> String {
+
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+
+pub fn hash_file(path: &Path) -> Result<String, io::Error> {
+    Ok(hash(&fs::read(path)?))
 }
 
 
@@ -49,35 +139,46 @@ impl File {
 
 #[derive(Debug)]
 pub enum FileError {
-    CopyError(std::io::Error),
-    NoFileNameError,
-    NoParentDirError,
-    FilenameInvalidUTFError,
+    CopyError(PathBuf, PathBuf, std::io::Error),
+    ReadError(PathBuf, std::io::Error),
+    MetadataCopyError(PathBuf, std::io::Error),
+    NoFileNameError(PathBuf),
+    NoParentDirError(PathBuf),
+    FilenameInvalidUTFError(PathBuf),
 }
 
-impl Error for FileError {}
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FileError::CopyError(_, _, io_error)
+                | FileError::ReadError(_, io_error)
+                | FileError::MetadataCopyError(_, io_error) => Some(io_error),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for FileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FileError::CopyError(copy_error) => {
-                write!(f, "{}", copy_error)
+            FileError::CopyError(source, destination, _) => {
+                write!(f, "Failed to copy {} to {}", source.display(), destination.display())
+            },
+            FileError::ReadError(path, _) => {
+                write!(f, "Failed to read {}", path.display())
+            },
+            FileError::MetadataCopyError(path, _) => {
+                write!(f, "Failed to set the mode and modification time of {}", path.display())
             },
-            FileError::FilenameInvalidUTFError => {
-                write!(f, "Invalild UTF in filename")
+            FileError::FilenameInvalidUTFError(path) => {
+                write!(f, "Invalild UTF in filename {}", path.display())
             },
-            FileError::NoParentDirError => {
-                write!(f, "File does not have a parent directory")
+            FileError::NoParentDirError(path) => {
+                write!(f, "File {} does not have a parent directory", path.display())
             }
-            FileError::NoFileNameError => {
-                write!(f, "File does not have a valid filename")
+            FileError::NoFileNameError(path) => {
+                write!(f, "File {} does not have a valid filename", path.display())
             },
         }
     }
 }
-
-impl From<std::io::Error> for FileError {
-    fn from(error: std::io::Error) -> FileError {
-        FileError::CopyError(error)
-    }
-}
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use ::ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+
+
+
+pub const IGNORE_FILE: &str = ".dotfilesignore";
+
+
+pub struct Ignore {
+    matcher: Gitignore,
+}
+
+impl Ignore {
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Ignore, IgnoreError> {
+
+        let mut builder = GitignoreBuilder::new(root);
+
+        for pattern in patterns.iter() {
+            builder.add_line(None, pattern)?;
+        }
+
+        Ok(Ignore { matcher: builder.build()? })
+    }
+
+
+    pub fn none() -> Ignore {
+        Ignore { matcher: Gitignore::empty() }
+    }
+
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+
+
+
+// Patterns from the manager folder's ignore file, which apply to every dotfile
+pub fn read_ignore_file(manager_dir: &Path) -> Result<Vec<String>, IgnoreError> {
+
+    let ignore_file = manager_dir.join(IGNORE_FILE);
+
+    let contents = match fs::read_to_string(&ignore_file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(IgnoreError::IgnoreFileError(ignore_file, e)),
+    };
+
+    let patterns = contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    Ok(patterns)
+}
+
+
+
+
+#[derive(Debug)]
+pub enum IgnoreError {
+    IgnoreFileError(PathBuf, std::io::Error),
+    IgnorePatternError(::ignore::Error),
+}
+
+impl Error for IgnoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IgnoreError::IgnoreFileError(_, io_error) => Some(io_error),
+            IgnoreError::IgnorePatternError(pattern_error) => Some(pattern_error),
+        }
+    }
+}
+
+impl fmt::Display for IgnoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreError::IgnoreFileError(path, _) => {
+                write!(f, "Failed to read {}", path.display())
+            },
+            IgnoreError::IgnorePatternError(_) => {
+                write!(f, "Ignore pattern is not valid")
+            },
+        }
+    }
+}
+
+impl From<::ignore::Error> for IgnoreError {
+    fn from(error: ::ignore::Error) -> IgnoreError {
+        IgnoreError::IgnorePatternError(error)
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io;
+
+use crate::fs::file::FileError;
+use crate::sync::plan::Operation;
+
+
+
+
+// A symlink inside a directory dotfile, copied as a link rather than as what it points to
+pub struct Link {
+    pub path: PathBuf,
+    pub filename: String,
+    pub target: PathBuf,
+}
+
+impl Link {
+    pub fn new(path: &Path) -> Result<Link, FileError> {
+
+        let filename = match path.file_name() {
+            Some(filename) => match filename.to_str() {
+                Some(filename) => String::from(filename),
+                None => return Err(FileError::FilenameInvalidUTFError(path.to_path_buf())),
+            },
+            None => return Err(FileError::NoFileNameError(path.to_path_buf())),
+        };
+
+        let target = fs::read_link(path).map_err(|e| FileError::ReadError(path.to_path_buf(), e))?;
+
+        Ok(Link { path: path.to_path_buf(), filename, target })
+    }
+
+
+    pub fn plan_copy(&self, dest_path: &Path) -> Result<Option<Operation>, FileError> {
+
+        let source = self.path.clone();
+        let target = self.target.clone();
+        let link = dest_path.to_path_buf();
+
+        let operation = match fs::read_link(dest_path) {
+            Ok(dest_target) if dest_target == self.target => None,
+            Ok(_) => Some(Operation::Symlink { source, target, link, replace: true }),
+            Err(_) if fs::symlink_metadata(dest_path).is_ok() => Some(Operation::Symlink { source, target, link, replace: true }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Some(Operation::Symlink { source, target, link, replace: false }),
+            Err(e) => return Err(FileError::ReadError(dest_path.to_path_buf(), e)),
+        };
+
+        Ok(operation)
+    }
+}
@@ -1,2 +1,4 @@
 pub mod dir;
 pub mod file;
+pub mod ignore;
+pub mod link;
@@ -1,35 +1,95 @@
 use std::error::Error;
-use std::fmt;
+use std::path::{self, Path, PathBuf};
+use std::{env, fmt, iter, slice};
 
 use itertools::{Itertools, Either};
 
 use crate::config::cfg;
 use crate::dotfile::dot;
 use crate::args::arg;
+use crate::sync::{backup, diff, plan, state, status};
+use crate::vcs::git;
+use crate::template::vars::Variables;
 
 pub mod config;
 pub mod dotfile;
 pub mod args;
 pub mod fs;
+pub mod sync;
+pub mod vcs;
+pub mod template;
 
 
 
 
 pub fn run(args: arg::Cli, config: cfg::Config) -> Result<(), ManagerError> {
 
-    let copy_to_sys = args.from;
+    let command = args.get_command();
 
-    let _dry_run = args.dry;
+    let backups = backup::Backups::new(&config.backup_dir);
 
-    let dotfiles = config.dotfiles;
+    let state_file = config.state_file();
 
-    let (valid, unrecoverable_errors): (Vec<_>, Vec<_>) = dotfiles.into_iter().partition_result();
+    let (entries, config_errors): (Vec<_>, Vec<_>) = config.dotfiles.iter().map(Result::as_ref).partition_result();
 
-    if unrecoverable_errors.len() > 0 {
+    if !config_errors.is_empty() {
+        for error in config_errors.into_iter() {
+            eprintln!("Invalid dotfile in config: {}", ErrorChain(error));
+        }
+        return Err(ManagerError::DotfileCreateError)
+    }
+
+    if let arg::Command::Add { path, manager_path, name, tags } = &command {
+        let entry = new_entry(&config, &entries, path, manager_path.as_deref(), name.as_deref(), tags)?;
+        return add(&config, entry, &args)
+    }
+
+    if let arg::Command::Forget { target, delete, restore_original } = &command {
+        return forget(&config, &entries, target, *delete, *restore_original, args.dry)
+    }
+
+    if let (Some(profile), true) = (&config.profile, args.verbose) {
+        println!("Using profile {}", profile.name);
+    }
+
+    // Add and forget see every dotfile in the config, the rest only those the profile selects
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter_map(|entry| match &config.profile {
+            Some(profile) => profile.apply(entry),
+            None => Some(entry.clone()),
+        })
+        .collect();
+
+    let repo = git::GitRepo::open(&config.manager_dir);
+
+    if let (arg::Command::Apply { pull, .. }, Some(repo)) = (&command, &repo) {
+        if (*pull || config.git.pull) && !args.dry {
+            repo.pull(&config.git.remote, config.git.branch.as_deref())?;
+        }
+    }
+
+    let (valid, unrecoverable_errors): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .map(|entry| entry.load(&config.manager_dir))
+        .partition_result();
+
+    if !unrecoverable_errors.is_empty() {
         for error in unrecoverable_errors.into_iter() {
-            eprintln!("{:#?}", error);
-            return Err(ManagerError::DotfileCreateError)
+            eprintln!("Failed to read dotfile: {}", ErrorChain(&error));
         }
+        return Err(ManagerError::DotfileCreateError)
+    }
+
+    let selection = command.selection();
+
+    let valid: Vec<_> = valid
+        .into_iter()
+        .filter(|dotfile| selection.matches(dotfile))
+        .collect();
+
+    if valid.is_empty() && !(selection.targets.is_empty() && selection.tags.is_empty()) {
+        return Err(ManagerError::NoDotfilesSelectedError)
     }
 
 
@@ -43,67 +103,570 @@ pub fn run(args: arg::Cli, config: cfg::Config) -> Result<(), ManagerError> {
             }
     );
 
-    if contains_errors.len() > 0 {
-        log_errored_dotfiles(&contains_errors).expect("Dotfile path is invalid"); 
+    if !contains_errors.is_empty() {
+        log_errored_dotfiles(&contains_errors);
+    }
+
+    // Dotfiles that failed to scan are left out of every command, but still make it fail
+    let failed = contains_errors.len();
+
+    let result = match command {
+        arg::Command::Collect { force, no_commit, .. } => {
+            let commit_repo = repo.as_ref().filter(|_| config.git.auto_commit && !no_commit);
+            sync(&error_free, false, force, &config, commit_repo, &args, failed)
+        },
+        arg::Command::Apply { force, .. } => sync(&error_free, true, force, &config, None, &args, failed),
+        arg::Command::Status { porcelain, .. } => print_status(&error_free, &state_file, &config.variables, args.verbose, porcelain, failed),
+        arg::Command::Diff { direction, stat, .. } => print_diff(&error_free, direction.to_system(), &config.variables, stat),
+        arg::Command::Restore { timestamp, list: true, .. } => list_backups(&error_free, &backups, timestamp.as_deref()),
+        arg::Command::Restore { timestamp, .. } => restore(&error_free, &backups, timestamp.as_deref(), args.dry),
+        // Handled before the dotfiles are loaded
+        arg::Command::Add { .. } | arg::Command::Forget { .. } => Ok(()),
+    };
+
+    match result {
+        Ok(()) if failed > 0 => Err(ManagerError::SyncFailedError(failed)),
+        result => result,
+    }
+}
+
+
+fn sync(dotfiles: &[dot::ManagedDotfile], copy_to_sys: bool, force: bool, config: &cfg::Config, commit_repo: Option<&git::GitRepo>, args: &arg::Cli, failed: usize) -> Result<(), ManagerError> {
+
+    let state_file = config.state_file();
+
+    let mut state = state::State::load(&state_file)?;
+
+    let plan = plan::Plan::new(dotfiles, copy_to_sys, &state, &config.variables, force);
+
+    for error in plan.errors.iter() {
+        println!("Failed to plan dotfile: {}", ErrorChain(error));
     }
 
-    let copy_results = error_free
+    if args.verbose {
+        dotfiles
+            .iter()
+            .flat_map(|dotfile| dotfile.get_skipped())
+            .for_each(|skipped| println!("{:<9} {}", "skip", skipped));
+    }
+
+    if args.dry || args.verbose {
+        plan.operations
+            .iter()
+            .filter(|operation| args.verbose || operation.is_change())
+            .for_each(|operation| println!("{}", operation));
+    }
+
+    let mut copy_errors = 0;
+
+    if !args.dry {
+        let report = plan::apply(&plan, &backup::Backups::new(&config.backup_dir).snapshot(), &mut state);
+
+        // Fallbacks are always worth knowing about, the strategy every file used only with --verbose
+        report.copied
+            .iter()
+            .filter(|copied| args.verbose || copied.fell_back())
+            .for_each(|copied| println!("{}", copied));
+
+        for error in report.errors.iter() {
+            println!("Failed to copy dotfile: {}", ErrorChain(error));
+        }
+
+        copy_errors = report.errors.len();
+
+        state.save(&state_file)?;
+
+        if let Some(repo) = commit_repo {
+            commit_collected(repo, dotfiles, &report)?;
+        }
+    }
+
+    let conflicts = plan.errors
+        .iter()
+        .filter(|error| matches!(error, dot::DotfileError::Conflict(_)))
+        .count();
+
+    let failures = failed + plan.errors.len() - conflicts + copy_errors;
+
+    if failures > 0 {
+        return Err(ManagerError::SyncFailedError(failures))
+    }
+
+    if conflicts > 0 {
+        return Err(ManagerError::DotfilesConflictError(conflicts))
+    }
+
+    Ok(())
+}
+
+
+fn commit_collected(repo: &git::GitRepo, dotfiles: &[dot::ManagedDotfile], report: &plan::ApplyReport) -> Result<(), ManagerError> {
+
+    let touched: Vec<_> = dotfiles
         .iter()
-        .map(|dotfile| dotfile.copy_dotfile(copy_to_sys));
+        .filter(|dotfile| report.changed.iter().any(|changed| changed.starts_with(dotfile.manager_dotfile.path())))
+        .collect();
+
+    if touched.is_empty() {
+        return Ok(())
+    }
+
+    let names: Vec<&str> = touched.iter().map(|dotfile| dotfile.options.name.as_str()).collect();
+
+    let mut message = format!("Collect {}\n\n", names.join(", "));
+
+    // Directories only show up for their permissions and mtimes, which git doesn't track
+    for changed in report.changed.iter().unique().filter(|changed| !changed.symlink_metadata().is_ok_and(|metadata| metadata.is_dir())) {
+        let path = changed.strip_prefix(&repo.path).unwrap_or(changed);
+        message.push_str(&format!("- {}\n", path.display()));
+    }
+
+    let paths: Vec<_> = touched.iter().map(|dotfile| dotfile.manager_dotfile.path().to_path_buf()).collect();
 
+    if repo.commit(&paths, &message)? {
+        println!("Committed {}", names.join(", "));
+    }
+
+    Ok(())
+}
+
+
+fn new_entry(config: &cfg::Config, entries: &[&cfg::DotfileEntry], path: &Path, manager_path: Option<&Path>, name: Option<&str>, tags: &[String]) -> Result<cfg::DotfileEntry, ManagerError> {
+
+    let system_path = path::absolute(path).map_err(|e| ManagerError::ManagerIOError("resolve", path.to_path_buf(), e))?;
+
+    if system_path.symlink_metadata().is_err() {
+        return Err(ManagerError::AddPathNotFoundError(system_path))
+    }
 
-    for result in copy_results {
-        match result {
-            Err(e) => println!("Failed to copy dotfile: {:?}", e),
-            _ => (),
+    if system_path.starts_with(&config.manager_dir) || config.manager_dir.starts_with(&system_path) {
+        return Err(ManagerError::ManagerDirOverlapError(system_path, config.manager_dir.clone()))
+    }
+
+    // A profile can move a dotfile elsewhere on this host, both places are taken
+    for entry in entries.iter() {
+        let profile_path = config.profile.as_ref().and_then(|profile| profile.system_paths.get(&entry.options.name));
+
+        for managed_path in iter::once(&entry.system_path).chain(profile_path) {
+            if system_path.starts_with(managed_path) {
+                return Err(ManagerError::AlreadyManagedError(system_path, entry.options.name.clone()))
+            }
+            if managed_path.starts_with(&system_path) {
+                return Err(ManagerError::ContainsDotfileError(system_path, entry.options.name.clone()))
+            }
         }
     }
 
+    let manager_path = match manager_path {
+        Some(manager_path) if manager_path.is_relative() => manager_path.to_path_buf(),
+        Some(manager_path) => match manager_path.strip_prefix(&config.manager_dir) {
+            Ok(manager_path) => manager_path.to_path_buf(),
+            Err(_) => return Err(ManagerError::ManagerPathOutsideError(manager_path.to_path_buf(), config.manager_dir.clone())),
+        },
+        None => {
+            let home_dir = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+
+            match system_path.strip_prefix(&home_dir) {
+                Ok(relative_path) => relative_path.to_path_buf(),
+                Err(_) => system_path.strip_prefix("/").unwrap_or(&system_path).to_path_buf(),
+            }
+        },
+    };
+
+    // `..` or the manager folder itself would put the dotfile outside of it
+    let inside = manager_path.components().all(|component| matches!(component, path::Component::Normal(_) | path::Component::CurDir));
 
+    if !inside || manager_path.file_name().is_none() {
+        return Err(ManagerError::ManagerPathOutsideError(config.manager_dir.join(&manager_path), config.manager_dir.clone()))
+    }
+
+    if config.manager_dir.join(&manager_path).symlink_metadata().is_ok() {
+        return Err(ManagerError::ManagerPathTakenError(config.manager_dir.join(&manager_path)))
+    }
+
+    let name = match name {
+        Some(name) => String::from(name),
+        None => match manager_path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => String::from(name),
+            None => return Err(ManagerError::DotfileNameError(manager_path)),
+        },
+    };
+
+    let options = dot::DotfileOptions { name, tags: tags.to_vec(), ..Default::default() };
+
+    Ok(cfg::DotfileEntry { index: None, manager_path, system_path, options })
+}
+
+
+fn add(config: &cfg::Config, entry: cfg::DotfileEntry, args: &arg::Cli) -> Result<(), ManagerError> {
+
+    let dotfile = entry.load(&config.manager_dir)?;
+
+    let state_file = config.state_file();
+
+    let mut state = state::State::load(&state_file)?;
+
+    let mut plan = plan::Plan::new(slice::from_ref(&dotfile), false, &state, &config.variables, true);
+
+    if let Some(error) = plan.errors.pop() {
+        return Err(ManagerError::from(error))
+    }
+
+    if args.dry || args.verbose {
+        plan.changes().for_each(|operation| println!("{}", operation));
+    }
+
+    if args.dry {
+        println!("{:<9} {} -> {}", "add", entry.options.name, config.path.display());
+        return Ok(())
+    }
+
+    let report = plan::apply(&plan, &backup::Backups::new(&config.backup_dir).snapshot(), &mut state);
+
+    if let Some(error) = report.errors.into_iter().next() {
+        return Err(ManagerError::PlanError(error))
+    }
+
+    state.save(&state_file)?;
+
+    config.append_dotfile(&entry)?;
+
+    println!("Added {} to {}", entry.options.name, config.path.display());
 
     Ok(())
 }
 
-fn log_errored_dotfiles(errors: &Vec<dot::ManagedDotfile>) -> Result<(), ManagerError> {
 
-    for error in errors.into_iter() {
+fn forget(config: &cfg::Config, entries: &[&cfg::DotfileEntry], target: &str, delete: bool, restore_original: bool, dry_run: bool) -> Result<(), ManagerError> {
 
-        if let dot::Dotfile::Dir(manager_dotfile) = &error.manager_dotfile {
-            let dot_path = manager_dotfile.path.to_str().unwrap();
-            println!("Error copying dotfile: {}", dot_path);
-            manager_dotfile.errors
-                .iter()
-                .for_each(|error| println!("Error: {:?}", error));
-        };
+    let target_path = path::absolute(target).map_err(|e| ManagerError::ManagerIOError("resolve", PathBuf::from(target), e))?;
+
+    // Any dotfile in the config can be forgotten, at the system path it has on this host
+    let forgotten: Vec<_> = entries
+        .iter()
+        .map(|entry| match &config.profile {
+            Some(profile) => profile.relocate((*entry).clone()),
+            None => (*entry).clone(),
+        })
+        .filter(|entry| {
+            entry.options.name == target
+                || entry.system_path == target_path
+                || config.manager_dir.join(&entry.manager_path) == target_path
+        })
+        .collect();
+
+    if forgotten.is_empty() {
+        return Err(ManagerError::NoDotfilesSelectedError)
+    }
+
+    // Names default to the last part of manager_path, so one name can stand for several dotfiles
+    if forgotten.len() > 1 {
+        let matches = forgotten.iter().map(|entry| config.manager_dir.join(&entry.manager_path)).collect();
+        return Err(ManagerError::AmbiguousDotfileError(String::from(target), matches))
+    }
+
+    let backups = backup::Backups::new(&config.backup_dir);
+    let current_backup = backups.snapshot();
+
+    let state_file = config.state_file();
+    let mut state = state::State::load(&state_file)?;
 
-        if let dot::Dotfile::Dir(system_dotfile) = &error.system_dotfile {
-            let Some(dot_path) = system_dotfile.path.to_str() else {
-                return Err(ManagerError::DotfileInvalidPathError)
-            };
+    for entry in forgotten.iter() {
+        let dotfile = entry.load(&config.manager_dir)?;
 
-            println!("Error copying dotfile: {}", dot_path);
-            system_dotfile.errors
-                .iter()
-                .for_each(|error| println!("Error: {:?}", error));
+        let system_path = dotfile.system_dotfile.path();
+        let manager_path = dotfile.manager_dotfile.path();
+
+        let original = if restore_original {
+            Some(original_backup(&backups, system_path)?.ok_or(ManagerError::BackupNotFoundError)?)
+        } else {
+            None
         };
 
+        // A link into the manager folder is replaced by a copy so the system keeps working
+        if dotfile.is_linked() {
+            println!("{:<9} {}", "unlink", system_path.display());
+
+            if !dry_run {
+                std::fs::remove_file(system_path)
+                    .map_err(|e| ManagerError::ManagerIOError("remove the link", system_path.to_path_buf(), e))?;
+                backup::copy_entry(manager_path, system_path)
+                    .map_err(|e| ManagerError::ManagerIOError("copy", manager_path.to_path_buf(), e))?;
+            }
+        }
+
+        if let Some(snapshot) = original {
+            restore(slice::from_ref(&dotfile), &backups, Some(&snapshot.timestamp), dry_run)?;
+        }
+
+        if delete && manager_path.exists() {
+            println!("{:<9} {}", "delete", manager_path.display());
+
+            if !dry_run {
+                current_backup.save(manager_path)
+                    .map_err(|e| ManagerError::ManagerIOError("back up", manager_path.to_path_buf(), e))?;
+            }
+        }
+
+        println!("{:<9} {}", "forget", entry.options.name);
+
+        state.forget(system_path);
+    }
+
+    if !dry_run {
+        let indices: Vec<usize> = forgotten.iter().filter_map(|entry| entry.index).collect();
+
+        config.remove_dotfiles(&indices)?;
+
+        state.save(&state_file)?;
     }
 
     Ok(())
 }
 
 
+fn original_backup(backups: &backup::Backups, system_path: &Path) -> Result<Option<backup::Snapshot>, ManagerError> {
+
+    for snapshot in list_snapshots(backups)? {
+        if snapshot_files(&snapshot)?.iter().any(|file| file.starts_with(system_path)) {
+            return Ok(Some(snapshot))
+        }
+    }
+
+    Ok(None)
+}
+
+
+fn backed_up_files(dotfiles: &[dot::ManagedDotfile], snapshot: &backup::Snapshot) -> Result<Vec<std::path::PathBuf>, ManagerError> {
+
+    let files = snapshot_files(snapshot)?
+        .into_iter()
+        .filter(|file| dotfiles.iter().any(|dotfile| {
+            file.starts_with(dotfile.system_dotfile.path()) || file.starts_with(dotfile.manager_dotfile.path())
+        }))
+        .collect();
+
+    Ok(files)
+}
+
+
+fn list_backups(dotfiles: &[dot::ManagedDotfile], backups: &backup::Backups, timestamp: Option<&str>) -> Result<(), ManagerError> {
+
+    let snapshots = match timestamp {
+        Some(timestamp) => vec![get_snapshot(backups, timestamp)?.ok_or(ManagerError::BackupNotFoundError)?],
+        None => list_snapshots(backups)?,
+    };
+
+    for snapshot in snapshots {
+        let files = backed_up_files(dotfiles, &snapshot)?;
+
+        if !files.is_empty() {
+            println!("{}", snapshot.timestamp);
+            files.iter().for_each(|file| println!("  {}", file.display()));
+        }
+    }
+
+    Ok(())
+}
+
+
+fn restore(dotfiles: &[dot::ManagedDotfile], backups: &backup::Backups, timestamp: Option<&str>, dry_run: bool) -> Result<(), ManagerError> {
+
+    let snapshot = match timestamp {
+        Some(timestamp) => get_snapshot(backups, timestamp)?,
+        None => {
+            let mut found = None;
+            for snapshot in list_snapshots(backups)?.into_iter().rev() {
+                if !backed_up_files(dotfiles, &snapshot)?.is_empty() {
+                    found = Some(snapshot);
+                    break
+                }
+            }
+            found
+        },
+    };
+
+    let Some(snapshot) = snapshot else {
+        return Err(ManagerError::BackupNotFoundError)
+    };
+
+    let current_backup = backups.snapshot();
+
+    for file in backed_up_files(dotfiles, &snapshot)? {
+        println!("{:<9} {} -> {}", "restore", snapshot.backup_path(&file).display(), file.display());
+
+        if dry_run {
+            continue
+        }
+
+        if file.exists() && current_backup.timestamp != snapshot.timestamp {
+            current_backup.save(&file).map_err(|e| ManagerError::ManagerIOError("back up", file.clone(), e))?;
+        }
+
+        snapshot.restore(&file).map_err(|e| ManagerError::ManagerIOError("restore", file.clone(), e))?;
+    }
+
+    Ok(())
+}
+
+
+fn list_snapshots(backups: &backup::Backups) -> Result<Vec<backup::Snapshot>, ManagerError> {
+    backups.list().map_err(|e| ManagerError::ManagerIOError("list backups in", backups.dir.clone(), e))
+}
+
+
+fn get_snapshot(backups: &backup::Backups, timestamp: &str) -> Result<Option<backup::Snapshot>, ManagerError> {
+    backups.get(timestamp).map_err(|e| ManagerError::ManagerIOError("list backups in", backups.dir.clone(), e))
+}
+
+
+fn snapshot_files(snapshot: &backup::Snapshot) -> Result<Vec<PathBuf>, ManagerError> {
+    snapshot.files().map_err(|e| ManagerError::ManagerIOError("read backup", snapshot.path.clone(), e))
+}
+
+
+// Dotfiles that failed to scan can't be compared, so they count as out of sync
+fn print_status(dotfiles: &[dot::ManagedDotfile], state_file: &Path, variables: &Variables, verbose: bool, porcelain: bool, failed: usize) -> Result<(), ManagerError> {
+
+    let state = state::State::load(state_file)?;
+
+    let mut out_of_sync = failed;
+
+    for dotfile in dotfiles.iter() {
+        let statuses = status::status(dotfile, &state, variables)?;
+
+        for entry in statuses.iter() {
+            if !entry.state.is_in_sync() {
+                out_of_sync += 1;
+            } else if !verbose && !porcelain {
+                continue
+            }
+
+            if porcelain {
+                println!("{}\t{}\t{}", entry.state, entry.system_path.display(), entry.manager_path.display());
+            } else {
+                println!("{}", entry);
+            }
+        }
+
+        for skipped in dotfile.get_skipped() {
+            if porcelain {
+                println!("skipped\t{}\t{}", skipped.path.display(), skipped.kind);
+            } else if verbose {
+                println!("{:<20} {}", "skipped", skipped);
+            }
+        }
+    }
+
+    if out_of_sync > 0 {
+        return Err(ManagerError::DotfilesOutOfSyncError(out_of_sync))
+    }
+
+    Ok(())
+}
+
+
+fn print_diff(dotfiles: &[dot::ManagedDotfile], copy_to_sys: bool, variables: &Variables, stat_only: bool) -> Result<(), ManagerError> {
+
+    let plan = plan::Plan::new(dotfiles, copy_to_sys, &state::State::default(), variables, true);
+
+    for error in plan.errors.iter() {
+        println!("Failed to plan dotfile: {}", ErrorChain(error));
+    }
+
+    let mut stats = Vec::new();
+
+    for file_diff in diff::diffs(&plan) {
+        let file_diff = file_diff?;
+
+        if stat_only {
+            stats.push(file_diff.stat());
+        } else {
+            print!("{}", file_diff);
+        }
+    }
+
+    if stat_only {
+        stats.iter().for_each(|stat| println!("{}", stat));
+        println!("{}", diff::summary(&stats));
+    }
+
+    Ok(())
+}
+
+
+fn log_errored_dotfiles(errors: &[dot::ManagedDotfile]) {
+
+    for dotfile in errors.iter() {
+        println!("Error copying dotfile: {}", dotfile.options.name);
+
+        for error in dotfile.get_dir_errors() {
+            println!("  {}", ErrorChain(error));
+        }
+    }
+}
+
+
+
+
+// Displays an error followed by the errors that caused it
+pub struct ErrorChain<'a>(pub &'a dyn Error);
+
+impl fmt::Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+
+        while let Some(error) = source {
+            write!(f, ": {}", error)?;
+            source = error.source();
+        }
+
+        Ok(())
+    }
+}
+
+
 
 
 #[derive(Debug)]
 pub enum ManagerError {
     DotfileCopyError(dot::DotfileError),
     ConfigParseError(cfg::ConfigParseError),
+    StateError(state::StateError),
+    GitError(git::GitError),
+    PlanError(plan::PlanError),
+    ManagerIOError(&'static str, PathBuf, std::io::Error),
     DotfileCreateError,
-    DotfileInvalidPathError,
+    DotfilesOutOfSyncError(usize),
+    NoDotfilesSelectedError,
+    BackupNotFoundError,
+    DotfilesConflictError(usize),
+    SyncFailedError(usize),
+    AddPathNotFoundError(PathBuf),
+    AlreadyManagedError(PathBuf, String),
+    ContainsDotfileError(PathBuf, String),
+    ManagerDirOverlapError(PathBuf, PathBuf),
+    ManagerPathOutsideError(PathBuf, PathBuf),
+    DotfileNameError(PathBuf),
+    ManagerPathTakenError(PathBuf),
+    AmbiguousDotfileError(String, Vec<PathBuf>),
 }
 
-impl Error for ManagerError {}
+impl Error for ManagerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ManagerError::DotfileCopyError(copy_error) => copy_error.source(),
+            ManagerError::ConfigParseError(parse_error) => parse_error.source(),
+            ManagerError::StateError(state_error) => state_error.source(),
+            ManagerError::GitError(git_error) => git_error.source(),
+            ManagerError::PlanError(plan_error) => plan_error.source(),
+            ManagerError::ManagerIOError(_, _, io_error) => Some(io_error),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for ManagerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -114,12 +677,64 @@ impl fmt::Display for ManagerError {
             ManagerError::ConfigParseError(parse_error) => {
                 write!(f, "{}", parse_error)
             },
+            ManagerError::StateError(state_error) => {
+                write!(f, "{}", state_error)
+            },
+            ManagerError::GitError(git_error) => {
+                write!(f, "{}", git_error)
+            },
+            ManagerError::PlanError(plan_error) => {
+                write!(f, "{}", plan_error)
+            },
+            ManagerError::ManagerIOError(action, path, _) => {
+                write!(f, "Failed to {} {}", action, path.display())
+            },
             ManagerError::DotfileCreateError => {
                 write!(f, "Failed to read dotfiles")
             }
-            ManagerError::DotfileInvalidPathError => {
-                write!(f, "Dotfile has an invalid path")
-            }
+            ManagerError::DotfilesOutOfSyncError(count) => {
+                write!(f, "{} dotfile entries are out of sync", count)
+            },
+            ManagerError::NoDotfilesSelectedError => {
+                write!(f, "No dotfiles match the given names, paths or tags")
+            },
+            ManagerError::BackupNotFoundError => {
+                write!(f, "No matching backup found")
+            },
+            ManagerError::DotfilesConflictError(count) => {
+                write!(f, "{} dotfiles were not synced because of conflicts", count)
+            },
+            ManagerError::SyncFailedError(count) => {
+                write!(f, "{} dotfiles or files could not be synced", count)
+            },
+            ManagerError::AddPathNotFoundError(path) => {
+                write!(f, "{} does not exist", path.display())
+            },
+            ManagerError::AlreadyManagedError(path, name) => {
+                write!(f, "{} is already managed by dotfile {}", path.display(), name)
+            },
+            ManagerError::ContainsDotfileError(path, name) => {
+                write!(f, "{} contains dotfile {}, which is already managed", path.display(), name)
+            },
+            ManagerError::ManagerDirOverlapError(path, manager_dir) => {
+                write!(f, "{} overlaps the manager folder {}", path.display(), manager_dir.display())
+            },
+            ManagerError::ManagerPathOutsideError(path, manager_dir) => {
+                write!(f, "Manager path {} is not inside the manager folder {}", path.display(), manager_dir.display())
+            },
+            ManagerError::DotfileNameError(path) => {
+                write!(f, "{} is not valid UTF-8 and can't name the dotfile, pass --name", path.display())
+            },
+            ManagerError::ManagerPathTakenError(path) => {
+                write!(f, "{} already exists in the manager folder, use --manager-path to put it elsewhere", path.display())
+            },
+            ManagerError::AmbiguousDotfileError(target, matches) => {
+                write!(f, "{} matches more than one dotfile, pass the path of the one to forget:", target)?;
+                for path in matches.iter() {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            },
         }
     }
 }
@@ -130,6 +745,18 @@ impl From<dot::DotfileError> for ManagerError {
     }
 }
 
+impl From<state::StateError> for ManagerError {
+    fn from(error: state::StateError) -> ManagerError {
+        ManagerError::StateError(error)
+    }
+}
+
+impl From<git::GitError> for ManagerError {
+    fn from(error: git::GitError) -> ManagerError {
+        ManagerError::GitError(error)
+    }
+}
+
 impl From<cfg::ConfigParseError> for ManagerError {
     fn from(error: cfg::ConfigParseError) -> ManagerError {
         ManagerError::ConfigParseError(error)
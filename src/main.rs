@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::process::ExitCode;
 
 use dotfiles_manager::args::arg;
 use dotfiles_manager::config::cfg;
@@ -6,11 +6,26 @@ use dotfiles_manager::config::cfg;
 
 
 
-fn main() -> Result<(), dotfiles_manager::ManagerError> {
+fn main() -> ExitCode {
+
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", dotfiles_manager::ErrorChain(&e));
+            ExitCode::FAILURE
+        }
+    }
+
+}
+
+
+fn run() -> Result<(), dotfiles_manager::ManagerError> {
 
     let cmd = arg::parse_args();
 
-    let program_config = cfg::Config::parse(PathBuf::from("/home/eesim/.config/dotfiles/config"))?;
+    let config_path = cfg::Config::find(cmd.config.clone())?;
+
+    let program_config = cfg::Config::parse(config_path, cmd.manager.clone(), cmd.profile.clone())?;
 
     dotfiles_manager::run(cmd, program_config)
 
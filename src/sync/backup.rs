@@ -0,0 +1,200 @@
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+use std::io;
+use std::os::unix::fs as unix_fs;
+
+use chrono::Local;
+
+
+
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+const TIMESTAMP_LENGTH: usize = "2024-01-31T12-00-00".len();
+
+
+pub struct Backups {
+    pub dir: PathBuf,
+}
+
+impl Backups {
+    pub fn new(dir: &Path) -> Backups {
+        Backups { dir: dir.to_path_buf() }
+    }
+
+
+    pub fn snapshot(&self) -> Snapshot {
+
+        let now = Local::now().format(TIMESTAMP_FORMAT).to_string();
+
+        let mut timestamp = now.clone();
+        let mut count = 0;
+
+        while self.dir.join(&timestamp).exists() {
+            count += 1;
+            timestamp = format!("{}-{}", now, count);
+        }
+
+        Snapshot { path: self.dir.join(&timestamp), timestamp }
+    }
+
+
+    pub fn list(&self) -> Result<Vec<Snapshot>, io::Error> {
+
+        if !self.dir.exists() {
+            return Ok(Vec::new())
+        }
+
+        let mut snapshots = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+
+            if let (true, Some(timestamp)) = (entry.file_type()?.is_dir(), entry.file_name().to_str()) {
+                snapshots.push(Snapshot { path: entry.path(), timestamp: String::from(timestamp) });
+            }
+        }
+
+        snapshots.sort_by(|a, b| order(&a.timestamp).cmp(&order(&b.timestamp)));
+
+        Ok(snapshots)
+    }
+
+
+    pub fn get(&self, timestamp: &str) -> Result<Option<Snapshot>, io::Error> {
+        Ok(self.list()?.into_iter().find(|snapshot| snapshot.timestamp == timestamp))
+    }
+}
+
+
+// Snapshots taken in the same second get a counter, which has to be compared as a number
+fn order(timestamp: &str) -> (&str, u64) {
+
+    let (time, count) = timestamp.split_at_checked(TIMESTAMP_LENGTH).unwrap_or((timestamp, ""));
+
+    (time, count.strip_prefix('-').and_then(|count| count.parse().ok()).unwrap_or(0))
+}
+
+
+
+
+pub struct Snapshot {
+    pub timestamp: String,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn backup_path(&self, original: &Path) -> PathBuf {
+
+        let relative: PathBuf = original
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+
+        self.path.join(relative)
+    }
+
+
+    pub fn save(&self, original: &Path) -> Result<PathBuf, io::Error> {
+
+        let backup_path = self.backup_path(original);
+
+        if let Some(parent_dir) = backup_path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+
+        if fs::rename(original, &backup_path).is_err() {
+            copy_entry(original, &backup_path)?;
+            remove_entry(original)?;
+        }
+
+        Ok(backup_path)
+    }
+
+
+    pub fn files(&self) -> Result<Vec<PathBuf>, io::Error> {
+
+        let mut files = Vec::new();
+        let mut dirs = vec![self.path.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+
+                if entry.file_type()?.is_dir() {
+                    dirs.push(entry.path());
+                } else if let Ok(relative) = entry.path().strip_prefix(&self.path) {
+                    files.push(Path::new("/").join(relative));
+                }
+            }
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+
+
+    pub fn restore(&self, original: &Path) -> Result<(), io::Error> {
+
+        if let Some(parent_dir) = original.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+
+        if fs::symlink_metadata(original).is_ok() {
+            remove_entry(original)?;
+        }
+
+        copy_entry(&self.backup_path(original), original)
+    }
+}
+
+
+
+
+pub fn copy_entry(from: &Path, to: &Path) -> Result<(), io::Error> {
+
+    let file_type = fs::symlink_metadata(from)?.file_type();
+
+    if file_type.is_symlink() {
+        unix_fs::symlink(fs::read_link(from)?, to)?;
+    } else if file_type.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+
+fn remove_entry(path: &Path) -> Result<(), io::Error> {
+
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn snapshots_in_the_same_second_are_ordered_by_counter() {
+
+        let mut timestamps = vec!["2024-01-31T12-00-00-10", "2024-01-31T12-00-01", "2024-01-31T12-00-00-2", "2024-01-31T12-00-00", "2024-01-31T12-00-00-9"];
+
+        timestamps.sort_by_key(|timestamp| order(timestamp));
+
+        assert_eq!(timestamps, ["2024-01-31T12-00-00", "2024-01-31T12-00-00-2", "2024-01-31T12-00-00-9", "2024-01-31T12-00-00-10", "2024-01-31T12-00-01"]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::fmt;
+use std::fs;
+
+use similar::{ChangeTag, TextDiff};
+
+use crate::dotfile::dot::DotfileError;
+use crate::fs::file;
+use crate::sync::plan::{Operation, Plan};
+
+
+
+
+const BINARY_CHECK_LEN: usize = 8000;
+const MAX_STAT_WIDTH: usize = 50;
+
+
+pub enum FileDiff {
+    Text { destination: String, source: String, old: String, new: String },
+    Binary { destination: String, source: String, old: Option<Vec<u8>>, new: Vec<u8> },
+}
+
+impl FileDiff {
+    pub fn new(source: &Path, destination: &Path) -> Result<FileDiff, DotfileError> {
+        FileDiff::with_contents(source, read(source)?, destination)
+    }
+
+
+    pub fn with_contents(source: &Path, new: Vec<u8>, destination: &Path) -> Result<FileDiff, DotfileError> {
+
+        let old = if destination.exists() {
+            Some(read(destination)?)
+        } else {
+            None
+        };
+
+        let source = source.display().to_string();
+        let destination = destination.display().to_string();
+
+        let old_text = match &old {
+            Some(old) => std::str::from_utf8(old).ok(),
+            None => Some(""),
+        };
+        let new_text = std::str::from_utf8(&new).ok();
+
+        let is_binary = is_binary(&new) || old.as_deref().is_some_and(is_binary);
+
+        let diff = match (is_binary, old_text, new_text) {
+            (false, Some(old_text), Some(new_text)) => FileDiff::Text {
+                destination,
+                source,
+                old: old_text.to_string(),
+                new: new_text.to_string(),
+            },
+            _ => FileDiff::Binary { destination, source, old, new },
+        };
+
+        Ok(diff)
+    }
+
+
+    pub fn stat(&self) -> DiffStat {
+
+        match self {
+            FileDiff::Text { destination, old, new, .. } => {
+                let diff = TextDiff::from_lines(old, new);
+
+                let (insertions, deletions) = diff.iter_all_changes().fold((0, 0), |(ins, del), change| {
+                    match change.tag() {
+                        ChangeTag::Insert => (ins + 1, del),
+                        ChangeTag::Delete => (ins, del + 1),
+                        ChangeTag::Equal => (ins, del),
+                    }
+                });
+
+                DiffStat { path: destination.clone(), insertions, deletions, binary: None }
+            },
+            FileDiff::Binary { destination, old, new, .. } => {
+                let old_len = old.as_ref().map_or(0, |old| old.len());
+                DiffStat { path: destination.clone(), insertions: 0, deletions: 0, binary: Some((old_len, new.len())) }
+            },
+        }
+    }
+}
+
+impl fmt::Display for FileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileDiff::Text { destination, source, old, new } => {
+                let diff = TextDiff::from_lines(old, new);
+                write!(f, "{}", diff.unified_diff().context_radius(3).header(destination, source))
+            },
+            FileDiff::Binary { destination, source, old, new } => {
+                match old {
+                    Some(old) => writeln!(f, "Binary file {} ({}) differs from {} ({})", destination, describe(old), source, describe(new)),
+                    None => writeln!(f, "Binary file {} ({}) would be created from {}", destination, describe(new), source),
+                }
+            },
+        }
+    }
+}
+
+
+
+
+pub struct DiffStat {
+    pub path: String,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub binary: Option<(usize, usize)>,
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.binary {
+            Some((old_len, new_len)) => write!(f, " {} | Bin {} -> {} bytes", self.path, old_len, new_len),
+            None => {
+                let changes = self.insertions + self.deletions;
+                let scale = |count: usize| if changes > MAX_STAT_WIDTH { count * MAX_STAT_WIDTH / changes } else { count };
+                write!(f, " {} | {} {}{}", self.path, changes, "+".repeat(scale(self.insertions)), "-".repeat(scale(self.deletions)))
+            },
+        }
+    }
+}
+
+
+
+
+pub fn diffs(plan: &Plan) -> Vec<Result<FileDiff, DotfileError>> {
+
+    plan.operations
+        .iter()
+        .filter_map(|operation| match operation {
+            Operation::Copy { source, destination, .. } | Operation::Overwrite { source, destination, .. } => {
+                Some(FileDiff::new(source, destination))
+            },
+            Operation::Render { source, destination, contents, .. } => {
+                Some(FileDiff::with_contents(source, contents.clone().into_bytes(), destination))
+            },
+            Operation::Delete { source, destination } if destination.is_file() => {
+                Some(FileDiff::with_contents(source, Vec::new(), destination))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+
+pub fn summary(stats: &[DiffStat]) -> String {
+
+    let insertions: usize = stats.iter().map(|stat| stat.insertions).sum();
+    let deletions: usize = stats.iter().map(|stat| stat.deletions).sum();
+
+    format!(" {} files changed, {} insertions(+), {} deletions(-)", stats.len(), insertions, deletions)
+}
+
+
+fn read(path: &Path) -> Result<Vec<u8>, DotfileError> {
+    fs::read(path).map_err(|e| DotfileError::DotfileReadError(path.to_path_buf(), e))
+}
+
+
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(BINARY_CHECK_LEN).any(|byte| *byte == 0)
+}
+
+
+fn describe(content: &[u8]) -> String {
+    format!("{} bytes, sha256 {}", content.len(), file::hash(content))
+}
@@ -0,0 +1,5 @@
+pub mod plan;
+pub mod status;
+pub mod diff;
+pub mod backup;
+pub mod state;
@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::{self as unix_fs, PermissionsExt};
+
+use filetime::FileTime;
+
+use crate::dotfile::dot::{self, ManagedDotfile};
+use crate::fs::file::{self, CopyStrategy};
+use crate::sync::backup::Snapshot;
+use crate::sync::state::State;
+use crate::template::vars::Variables;
+
+
+
+
+pub enum Operation {
+    Mkdir { path: PathBuf },
+    Copy { source: PathBuf, destination: PathBuf, strategy: CopyStrategy },
+    Overwrite { source: PathBuf, destination: PathBuf, strategy: CopyStrategy },
+    Skip { source: PathBuf, destination: PathBuf },
+    Symlink { source: PathBuf, target: PathBuf, link: PathBuf, replace: bool },
+    Render { source: PathBuf, destination: PathBuf, contents: String, replace: bool },
+    Chmod { path: PathBuf, mode: u32 },
+    SetMetadata { path: PathBuf, mode: u32, mtime: FileTime },
+    Delete { source: PathBuf, destination: PathBuf },
+}
+
+impl Operation {
+    pub fn is_change(&self) -> bool {
+        !matches!(self, Operation::Skip { .. })
+    }
+
+
+    pub fn file_paths(&self) -> Option<(&Path, &Path)> {
+        match self {
+            Operation::Copy { source, destination, .. }
+                | Operation::Overwrite { source, destination, .. }
+                | Operation::Skip { source, destination }
+                | Operation::Render { source, destination, .. } => Some((source, destination)),
+            Operation::Mkdir { .. }
+                | Operation::Symlink { .. }
+                | Operation::Chmod { .. }
+                | Operation::SetMetadata { .. }
+                | Operation::Delete { .. } => None,
+        }
+    }
+
+
+    pub fn destination(&self) -> &Path {
+        match self {
+            Operation::Copy { destination, .. }
+                | Operation::Overwrite { destination, .. }
+                | Operation::Skip { destination, .. }
+                | Operation::Render { destination, .. }
+                | Operation::Delete { destination, .. } => destination,
+            Operation::Symlink { link, .. } => link,
+            Operation::Mkdir { path }
+                | Operation::Chmod { path, .. }
+                | Operation::SetMetadata { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Mkdir { path } => {
+                write!(f, "{:<9} {}", "mkdir", path.display())
+            },
+            Operation::Copy { source, destination, strategy } => {
+                write!(f, "{:<9} {} -> {}", "copy", source.display(), destination.display())?;
+                write_strategy(f, *strategy)
+            },
+            Operation::Overwrite { source, destination, strategy } => {
+                write!(f, "{:<9} {} -> {}", "overwrite", source.display(), destination.display())?;
+                write_strategy(f, *strategy)
+            },
+            Operation::Skip { source, destination } => {
+                write!(f, "{:<9} {} -> {}", "skip", source.display(), destination.display())
+            },
+            Operation::Symlink { target, link, replace, .. } => {
+                let action = if *replace { "relink" } else { "symlink" };
+                write!(f, "{:<9} {} -> {}", action, link.display(), target.display())
+            },
+            Operation::Render { source, destination, .. } => {
+                write!(f, "{:<9} {} -> {}", "render", source.display(), destination.display())
+            },
+            Operation::Chmod { path, mode } => {
+                write!(f, "{:<9} {} ({:04o})", "chmod", path.display(), mode)
+            },
+            Operation::SetMetadata { path, mode, .. } => {
+                write!(f, "{:<9} {} ({:04o})", "metadata", path.display(), mode)
+            },
+            Operation::Delete { destination, .. } => {
+                write!(f, "{:<9} {}", "delete", destination.display())
+            },
+        }
+    }
+}
+
+
+
+
+fn write_strategy(f: &mut fmt::Formatter<'_>, strategy: CopyStrategy) -> fmt::Result {
+    match strategy {
+        CopyStrategy::Copy => Ok(()),
+        strategy => write!(f, " ({})", strategy),
+    }
+}
+
+
+
+
+pub struct Copied {
+    pub destination: PathBuf,
+    pub requested: CopyStrategy,
+    pub used: CopyStrategy,
+}
+
+impl Copied {
+    pub fn fell_back(&self) -> bool {
+        self.requested != self.used
+    }
+}
+
+impl fmt::Display for Copied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<9} {}", self.used, self.destination.display())?;
+
+        if self.fell_back() {
+            write!(f, " ({} not possible)", self.requested)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[derive(Default)]
+pub struct ApplyReport {
+    pub copied: Vec<Copied>,
+    // Destinations of every change that was applied, including deletes, links and chmods
+    pub changed: Vec<PathBuf>,
+    pub errors: Vec<PlanError>,
+}
+
+
+
+
+pub struct Plan {
+    pub operations: Vec<Operation>,
+    pub errors: Vec<dot::DotfileError>,
+    pub to_sys: bool,
+}
+
+impl Plan {
+    pub fn new(dotfiles: &[ManagedDotfile], to_sys: bool, state: &State, variables: &Variables, force: bool) -> Plan {
+
+        let mut operations = Vec::new();
+        let mut errors = Vec::new();
+
+        for dotfile in dotfiles.iter() {
+            match dotfile.plan_copy(to_sys, state, variables, force) {
+                Ok(dotfile_operations) => operations.extend(dotfile_operations),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        Plan { operations, errors, to_sys }
+    }
+
+
+    pub fn changes(&self) -> impl Iterator<Item = &Operation> {
+        self.operations.iter().filter(|operation| operation.is_change())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for operation in self.operations.iter() {
+            writeln!(f, "{}", operation)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+
+
+pub fn apply(plan: &Plan, backup: &Snapshot, state: &mut State) -> ApplyReport {
+
+    let mut report = ApplyReport::default();
+
+    for operation in plan.operations.iter() {
+        let result = apply_operation(operation, backup)
+            .and_then(|copied| record_operation(operation, plan.to_sys, state).map(|_| copied));
+
+        match result {
+            Ok(copied) => {
+                if operation.is_change() {
+                    report.changed.push(operation.destination().to_path_buf());
+                }
+                report.copied.extend(copied);
+            },
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    report
+}
+
+
+fn record_operation(operation: &Operation, to_sys: bool, state: &mut State) -> Result<(), PlanError> {
+
+    // Hashing the destination also covers rendered templates, whose source differs from the result
+    if let Some((source, destination)) = operation.file_paths() {
+        let system_path = if to_sys { destination } else { source };
+        let hash = file::hash_file(destination).map_err(|e| PlanError::PlanIOError("hash", destination.to_path_buf(), e))?;
+        state.record(system_path, hash);
+    }
+
+    // Links aren't hashed, a file they replaced shouldn't be compared against later
+    if let Operation::Delete { source, destination } | Operation::Symlink { source, link: destination, .. } = operation {
+        state.forget(if to_sys { destination } else { source });
+    }
+
+    Ok(())
+}
+
+
+fn apply_operation(operation: &Operation, backup: &Snapshot) -> Result<Option<Copied>, PlanError> {
+
+    let (source, destination, strategy, overwrite) = match operation {
+        Operation::Copy { source, destination, strategy } => (source, destination, *strategy, false),
+        Operation::Overwrite { source, destination, strategy } => (source, destination, *strategy, true),
+        Operation::Symlink { target, link, replace, .. } => {
+            if *replace {
+                save_backup(backup, link)?;
+            }
+            unix_fs::symlink(target, link).map_err(|e| PlanError::PlanIOError("create the link", link.clone(), e))?;
+            return Ok(None)
+        },
+        Operation::Render { source, destination, contents, replace } => {
+            if *replace {
+                save_backup(backup, destination)?;
+            }
+            fs::write(destination, contents).map_err(|e| PlanError::PlanIOError("write", destination.clone(), e))?;
+            let permissions = fs::metadata(source).map_err(|e| PlanError::PlanIOError("read", source.clone(), e))?.permissions();
+            set_permissions(destination, permissions)?;
+            return Ok(None)
+        },
+        Operation::Mkdir { path } => {
+            fs::create_dir_all(path).map_err(|e| PlanError::PlanIOError("create the directory", path.clone(), e))?;
+            return Ok(None)
+        },
+        Operation::Chmod { path, mode } => {
+            set_permissions(path, fs::Permissions::from_mode(*mode))?;
+            return Ok(None)
+        },
+        Operation::SetMetadata { path, mode, mtime } => {
+            set_permissions(path, fs::Permissions::from_mode(*mode))?;
+            filetime::set_file_mtime(path, *mtime).map_err(|e| PlanError::PlanIOError("set the modification time of", path.clone(), e))?;
+            return Ok(None)
+        },
+        // Deleted entries are moved into the backup, so they can be restored like overwritten files
+        Operation::Delete { destination, .. } => {
+            save_backup(backup, destination)?;
+            return Ok(None)
+        },
+        Operation::Skip { .. } => return Ok(None),
+    };
+
+    let source_file = file::File::new(source)?;
+
+    if overwrite {
+        save_backup(backup, destination)?;
+    }
+
+    let used = source_file.copy(destination, strategy)?;
+
+    Ok(Some(Copied { destination: destination.clone(), requested: strategy, used }))
+}
+
+
+fn save_backup(backup: &Snapshot, path: &Path) -> Result<PathBuf, PlanError> {
+    backup.save(path).map_err(|e| PlanError::PlanIOError("back up", path.to_path_buf(), e))
+}
+
+
+fn set_permissions(path: &Path, permissions: fs::Permissions) -> Result<(), PlanError> {
+    fs::set_permissions(path, permissions).map_err(|e| PlanError::PlanIOError("set the permissions of", path.to_path_buf(), e))
+}
+
+
+
+
+#[derive(Debug)]
+pub enum PlanError {
+    PlanIOError(&'static str, PathBuf, std::io::Error),
+    PlanFileCopyError(file::FileError),
+}
+
+impl Error for PlanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PlanError::PlanIOError(_, _, io_error) => Some(io_error),
+            PlanError::PlanFileCopyError(copy_error) => copy_error.source(),
+        }
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::PlanIOError(action, path, _) => {
+                write!(f, "Failed to {} {}", action, path.display())
+            },
+            PlanError::PlanFileCopyError(copy_error) => {
+                write!(f, "{}", copy_error)
+            },
+        }
+    }
+}
+
+impl From<file::FileError> for PlanError {
+    fn from(error: file::FileError) -> PlanError {
+        PlanError::PlanFileCopyError(error)
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+
+
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl State {
+    pub fn load(path: &Path) -> Result<State, StateError> {
+
+        if !path.exists() {
+            return Ok(State::default())
+        }
+
+        let contents = fs::read_to_string(path).map_err(|e| StateError::StateIOError(path.to_path_buf(), e))?;
+
+        toml::from_str(&contents).map_err(|e| StateError::StateReadError(path.to_path_buf(), e))
+    }
+
+
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir).map_err(|e| StateError::StateIOError(parent_dir.to_path_buf(), e))?;
+        }
+
+        fs::write(path, toml::to_string(self)?).map_err(|e| StateError::StateIOError(path.to_path_buf(), e))?;
+
+        Ok(())
+    }
+
+
+    pub fn get(&self, system_path: &Path) -> Option<&str> {
+        self.files.get(system_path).map(String::as_str)
+    }
+
+
+    pub fn record(&mut self, system_path: &Path, hash: String) {
+        self.files.insert(system_path.to_path_buf(), hash);
+    }
+
+
+    pub fn recorded_under<'a>(&'a self, system_path: &'a Path) -> impl Iterator<Item = (&'a Path, &'a str)> {
+        self.files
+            .iter()
+            .filter(move |(path, _)| path.starts_with(system_path))
+            .map(|(path, hash)| (path.as_path(), hash.as_str()))
+    }
+
+
+    pub fn forget(&mut self, system_path: &Path) {
+        self.files.retain(|path, _| !path.starts_with(system_path));
+    }
+}
+
+
+
+
+#[derive(Debug)]
+pub enum StateError {
+    StateIOError(PathBuf, std::io::Error),
+    StateReadError(PathBuf, toml::de::Error),
+    StateWriteError(toml::ser::Error),
+}
+
+impl Error for StateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StateError::StateIOError(_, io_error) => Some(io_error),
+            StateError::StateReadError(_, read_error) => Some(read_error),
+            StateError::StateWriteError(write_error) => Some(write_error),
+        }
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::StateIOError(path, _) => {
+                write!(f, "Failed to access sync state {}", path.display())
+            },
+            StateError::StateReadError(path, _) => {
+                write!(f, "Sync state file {} is not valid", path.display())
+            },
+            StateError::StateWriteError(_) => {
+                write!(f, "Failed to write sync state")
+            },
+        }
+    }
+}
+
+impl From<toml::ser::Error> for StateError {
+    fn from(error: toml::ser::Error) -> StateError {
+        StateError::StateWriteError(error)
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::dotfile::dot::{DeployMode, Dotfile, DotfileError, ManagedDotfile};
+use crate::fs::dir::Directory;
+use crate::fs::file;
+use crate::fs::link::Link;
+use crate::sync::state::State;
+use crate::template::vars::Variables;
+
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    InSync,
+    ModifiedOnSystem,
+    ModifiedInManager,
+    Conflict,
+    MissingOnSystem,
+    MissingInManager,
+    DeletedOnSystem,
+    DeletedInManager,
+    Missing,
+    TypeMismatch,
+    LinkMissing,
+    LinkBroken,
+    LinkForeign,
+    ModeChanged(u32, u32),
+}
+
+impl SyncState {
+    pub fn is_in_sync(&self) -> bool {
+        *self == SyncState::InSync
+    }
+}
+
+impl fmt::Display for SyncState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncState::InSync => f.pad("in-sync"),
+            SyncState::ModifiedOnSystem => f.pad("modified-on-system"),
+            SyncState::ModifiedInManager => f.pad("modified-in-manager"),
+            SyncState::Conflict => f.pad("conflict"),
+            SyncState::MissingOnSystem => f.pad("missing-on-system"),
+            SyncState::MissingInManager => f.pad("missing-in-manager"),
+            SyncState::DeletedOnSystem => f.pad("deleted-on-system"),
+            SyncState::DeletedInManager => f.pad("deleted-in-manager"),
+            SyncState::Missing => f.pad("missing"),
+            SyncState::TypeMismatch => f.pad("type-mismatch"),
+            SyncState::LinkMissing => f.pad("link-missing"),
+            SyncState::LinkBroken => f.pad("link-broken"),
+            SyncState::LinkForeign => f.pad("link-foreign"),
+            SyncState::ModeChanged(system, expected) => f.pad(&format!("mode {:04o} != {:04o}", system, expected)),
+        }
+    }
+}
+
+
+
+
+pub struct EntryStatus {
+    pub system_path: PathBuf,
+    pub manager_path: PathBuf,
+    pub state: SyncState,
+}
+
+impl fmt::Display for EntryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<20} {} <-> {}", self.state, self.system_path.display(), self.manager_path.display())
+    }
+}
+
+
+
+
+enum Entry<'a> {
+    File,
+    Dir(&'a Directory),
+    Link(&'a Link),
+}
+
+
+struct Comparison<'a> {
+    state: &'a State,
+    template: Option<&'a Variables>,
+    mode_bits: Option<u32>,
+    mirror: bool,
+}
+
+impl Comparison<'_> {
+    fn was_synced(&self, system_path: &Path) -> bool {
+        self.mirror && self.state.recorded_under(system_path).next().is_some()
+    }
+}
+
+
+pub fn status(dotfile: &ManagedDotfile, state: &State, variables: &Variables) -> Result<Vec<EntryStatus>, DotfileError> {
+
+    if dotfile.options.mode == DeployMode::Symlink && !dotfile.options.template {
+        return link_status(dotfile)
+            .map(|entry| vec![entry])
+            .map_err(|e| DotfileError::DotfileReadError(dotfile.system_dotfile.path().to_path_buf(), e))
+    }
+
+    // Templates are compared by what they render to rather than their source
+    let comparison = Comparison {
+        state,
+        template: Some(variables).filter(|_| dotfile.options.template),
+        mode_bits: dotfile.options.mode_bits,
+        mirror: dotfile.options.mirror,
+    };
+
+    let mut statuses = Vec::new();
+
+    match (&dotfile.system_dotfile, &dotfile.manager_dotfile) {
+        (Dotfile::Dir(system_dir), Dotfile::Dir(manager_dir)) => {
+            compare_dirs(system_dir, manager_dir, &comparison, &mut statuses)?
+        },
+        (Dotfile::File(system_file), Dotfile::File(manager_file)) => {
+            statuses.push(EntryStatus {
+                system_path: system_file.path.clone(),
+                manager_path: manager_file.path.clone(),
+                state: compare_paths(&system_file.path, &manager_file.path, &comparison)?,
+            });
+        },
+        (system_dotfile, manager_dotfile) => {
+            statuses.push(EntryStatus {
+                system_path: system_dotfile.path().to_path_buf(),
+                manager_path: manager_dotfile.path().to_path_buf(),
+                state: SyncState::TypeMismatch,
+            });
+        },
+    };
+
+    Ok(statuses)
+}
+
+
+fn link_status(dotfile: &ManagedDotfile) -> Result<EntryStatus, io::Error> {
+
+    let system_path = dotfile.system_dotfile.path();
+    let manager_path = dotfile.manager_dotfile.path();
+
+    let state = match fs::read_link(system_path) {
+        Ok(target) if target != manager_path => SyncState::LinkForeign,
+        Ok(_) if !manager_path.exists() => SyncState::LinkBroken,
+        Ok(_) => SyncState::InSync,
+        Err(e) if e.kind() == io::ErrorKind::NotFound || e.kind() == io::ErrorKind::InvalidInput => SyncState::LinkMissing,
+        Err(e) => return Err(e),
+    };
+
+    Ok(EntryStatus { system_path: system_path.to_path_buf(), manager_path: manager_path.to_path_buf(), state })
+}
+
+
+fn compare_paths(system_path: &Path, manager_path: &Path, comparison: &Comparison) -> Result<SyncState, DotfileError> {
+
+    let system_metadata = metadata(system_path)?;
+    let manager_metadata = metadata(manager_path)?;
+
+    let sync_state = match (system_metadata, manager_metadata) {
+        (None, None) => SyncState::Missing,
+        (None, Some(_)) => SyncState::MissingOnSystem,
+        (Some(_), None) => SyncState::MissingInManager,
+        (Some(system), Some(manager)) if system.is_dir() != manager.is_dir() => SyncState::TypeMismatch,
+        (Some(system), Some(manager)) => {
+            let read_error = |path: &Path, e| DotfileError::DotfileReadError(path.to_path_buf(), e);
+
+            let system_hash = file::hash_file(system_path).map_err(|e| read_error(system_path, e))?;
+            let manager_hash = match comparison.template {
+                Some(variables) => file::hash(ManagedDotfile::render(manager_path, variables)?.as_bytes()),
+                None => file::hash_file(manager_path).map_err(|e| read_error(manager_path, e))?,
+            };
+
+            let system_mode = system.permissions().mode() & file::MODE_MASK;
+            let expected_mode = comparison.mode_bits.unwrap_or(manager.permissions().mode() & file::MODE_MASK);
+
+            match comparison.state.get(system_path) {
+                _ if system_hash == manager_hash && system_mode != expected_mode => SyncState::ModeChanged(system_mode, expected_mode),
+                _ if system_hash == manager_hash => SyncState::InSync,
+                Some(synced_hash) if synced_hash == manager_hash => SyncState::ModifiedOnSystem,
+                Some(synced_hash) if synced_hash == system_hash => SyncState::ModifiedInManager,
+                Some(_) => SyncState::Conflict,
+                None if system.modified().map_err(|e| read_error(system_path, e))? > manager.modified().map_err(|e| read_error(manager_path, e))? => SyncState::ModifiedOnSystem,
+                None => SyncState::ModifiedInManager,
+            }
+        },
+    };
+
+    Ok(sync_state)
+}
+
+
+fn metadata(path: &Path) -> Result<Option<fs::Metadata>, DotfileError> {
+    match fs::metadata(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(DotfileError::DotfileReadError(path.to_path_buf(), e)),
+    }
+}
+
+
+fn entries(dir: &Directory) -> BTreeMap<&str, Entry<'_>> {
+
+    let files = dir.files
+        .iter()
+        .map(|file| (file.filename.as_str(), Entry::File));
+
+    let dirs = dir.directories
+        .iter()
+        .filter_map(|dir| Some((dir.path.file_name()?.to_str()?, Entry::Dir(dir))));
+
+    let links = dir.links
+        .iter()
+        .map(|link| (link.filename.as_str(), Entry::Link(link)));
+
+    files.chain(dirs).chain(links).collect()
+}
+
+
+fn compare_dirs(system_dir: &Directory, manager_dir: &Directory, comparison: &Comparison, statuses: &mut Vec<EntryStatus>) -> Result<(), DotfileError> {
+
+    if !system_dir.path.exists() || !manager_dir.path.exists() {
+        statuses.push(EntryStatus {
+            system_path: system_dir.path.clone(),
+            manager_path: manager_dir.path.clone(),
+            state: compare_paths(&system_dir.path, &manager_dir.path, comparison)?,
+        });
+        return Ok(())
+    }
+
+    let system_entries = entries(system_dir);
+    let manager_entries = entries(manager_dir);
+
+    let mut names: Vec<&str> = system_entries.keys().chain(manager_entries.keys()).copied().collect();
+    names.sort();
+    names.dedup();
+
+    for name in names {
+        let system_path = system_dir.path.join(name);
+        let manager_path = manager_dir.path.join(name);
+
+        let sync_state = match (system_entries.get(name), manager_entries.get(name)) {
+            (Some(Entry::Dir(system_subdir)), Some(Entry::Dir(manager_subdir))) => {
+                compare_dirs(system_subdir, manager_subdir, comparison, statuses)?;
+                continue
+            },
+            (Some(Entry::File), Some(Entry::File)) => compare_paths(&system_path, &manager_path, comparison)?,
+            (Some(Entry::Link(system_link)), Some(Entry::Link(manager_link))) if system_link.target == manager_link.target => SyncState::InSync,
+            (Some(Entry::Link(_)), Some(Entry::Link(_))) => SyncState::LinkForeign,
+            (Some(_), Some(_)) => SyncState::TypeMismatch,
+            // Mirrored entries that were synced before have been deleted on the other side
+            (Some(_), None) if comparison.was_synced(&system_path) => SyncState::DeletedInManager,
+            (None, Some(_)) if comparison.was_synced(&system_path) => SyncState::DeletedOnSystem,
+            (Some(_), None) => SyncState::MissingInManager,
+            (None, Some(_)) => SyncState::MissingOnSystem,
+            (None, None) => SyncState::Missing,
+        };
+
+        statuses.push(EntryStatus { system_path, manager_path, state: sync_state });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,591 @@
+use std::error::Error;
+use std::iter::Peekable;
+use std::fmt;
+use std::slice;
+use std::vec;
+
+use toml::Value;
+
+use crate::template::vars::Variables;
+
+
+
+
+enum Token {
+    Text(String),
+    Print(String, usize),
+    Tag(String, usize),
+}
+
+
+enum Node {
+    Text(String),
+    Print(Expression, usize),
+    If { branches: Vec<(Expression, usize, Vec<Node>)>, otherwise: Vec<Node> },
+    For { name: String, iterable: Expression, source: String, line: usize, body: Vec<Node> },
+}
+
+
+// The tag that ended a block, with its line, or None at the end of the template
+type EndTag = Option<(String, usize)>;
+
+
+enum Expression {
+    Literal(Value),
+    Variable(String),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Equals(Box<Expression>, Box<Expression>),
+    NotEquals(Box<Expression>, Box<Expression>),
+}
+
+
+struct Scope<'a> {
+    variables: &'a Variables,
+    locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+    fn get(&self, name: &str) -> Option<&Value> {
+
+        let (first, rest) = match name.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+
+        let Some((_, local)) = self.locals.iter().rev().find(|(local, _)| local == first) else {
+            return self.variables.get(name)
+        };
+
+        let mut value = local;
+
+        for key in rest.into_iter().flat_map(|rest| rest.split('.')) {
+            value = value.as_table()?.get(key)?;
+        }
+
+        Some(value)
+    }
+}
+
+
+
+
+pub fn render(template: &str, variables: &Variables) -> Result<String, TemplateError> {
+
+    let mut tokens = tokenize(template)?.into_iter();
+
+    let nodes = match parse_nodes(&mut tokens)? {
+        (nodes, None) => nodes,
+        (_, Some((tag, line))) => return Err(unexpected_tag(&tag, line)),
+    };
+
+    let mut scope = Scope { variables, locals: Vec::new() };
+    let mut output = String::new();
+
+    render_nodes(&nodes, &mut scope, &mut output)?;
+
+    Ok(output)
+}
+
+
+fn tokenize(template: &str) -> Result<Vec<Token>, TemplateError> {
+
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    let mut line = 1;
+    let mut at_line_start = true;
+
+    while let Some(start) = find_opening(rest) {
+        let (mut text, after) = rest.split_at(start);
+        let opening = &after[..2];
+        let closing = match opening {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+
+        let tag_line = line + text.matches('\n').count();
+
+        let Some(length) = after[2..].find(closing) else {
+            return Err(TemplateError::TemplateSyntaxError(format!("`{}` is never closed", opening), tag_line))
+        };
+
+        let inner = &after[2..2 + length];
+        rest = &after[2 + length + 2..];
+        line = tag_line + inner.matches('\n').count();
+
+        // Tags and comments on a line of their own don't leave an empty line behind
+        if opening != "{{" {
+            let line_begin = text.rfind('\n').map_or(0, |index| index + 1);
+            let line_end = rest.find('\n');
+
+            let starts_line = (line_begin > 0 || at_line_start) && text[line_begin..].trim().is_empty();
+            let ends_line = rest[..line_end.unwrap_or(rest.len())].trim().is_empty();
+
+            at_line_start = starts_line && ends_line;
+
+            if at_line_start {
+                text = &text[..line_begin];
+                rest = match line_end {
+                    Some(index) => {
+                        line += 1;
+                        &rest[index + 1..]
+                    },
+                    None => "",
+                };
+            }
+        } else {
+            at_line_start = false;
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(String::from(text)));
+        }
+
+        match opening {
+            "{{" => tokens.push(Token::Print(String::from(inner.trim()), tag_line)),
+            "{%" => tokens.push(Token::Tag(String::from(inner.trim()), tag_line)),
+            _ => (),
+        }
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(String::from(rest)));
+    }
+
+    Ok(tokens)
+}
+
+
+fn find_opening(text: &str) -> Option<usize> {
+    ["{{", "{%", "{#"].iter().filter_map(|opening| text.find(opening)).min()
+}
+
+
+fn split_keyword(tag: &str) -> (&str, &str) {
+    match tag.split_once(char::is_whitespace) {
+        Some((keyword, arguments)) => (keyword, arguments.trim()),
+        None => (tag, ""),
+    }
+}
+
+
+fn unexpected_tag(tag: &str, line: usize) -> TemplateError {
+    TemplateError::TemplateSyntaxError(format!("Unexpected `{{% {} %}}`", tag), line)
+}
+
+
+fn parse_nodes(tokens: &mut vec::IntoIter<Token>) -> Result<(Vec<Node>, EndTag), TemplateError> {
+
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Print(expression, line) => nodes.push(Node::Print(parse_expression(&expression, line)?, line)),
+            Token::Tag(tag, line) => match split_keyword(&tag) {
+                ("if", condition) => nodes.push(parse_if(condition, line, tokens)?),
+                ("for", arguments) => nodes.push(parse_for(arguments, line, tokens)?),
+                ("elif" | "else" | "endif" | "endfor", _) => return Ok((nodes, Some((tag, line)))),
+                (keyword, _) => return Err(TemplateError::TemplateSyntaxError(format!("Unknown tag `{}`", keyword), line)),
+            },
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+
+fn parse_if(condition: &str, line: usize, tokens: &mut vec::IntoIter<Token>) -> Result<Node, TemplateError> {
+
+    let mut branches = Vec::new();
+    let mut condition = (parse_expression(condition, line)?, line);
+
+    loop {
+        let (body, end) = parse_nodes(tokens)?;
+
+        let Some((tag, end_line)) = end else {
+            return Err(TemplateError::TemplateSyntaxError(String::from("`{% if %}` is never closed with `{% endif %}`"), line))
+        };
+
+        branches.push((condition.0, condition.1, body));
+
+        match split_keyword(&tag) {
+            ("elif", next_condition) => condition = (parse_expression(next_condition, end_line)?, end_line),
+            ("else", "") => {
+                let otherwise = match parse_nodes(tokens)? {
+                    (otherwise, Some((tag, _))) if tag == "endif" => otherwise,
+                    (_, Some((tag, end_line))) => return Err(unexpected_tag(&tag, end_line)),
+                    (_, None) => return Err(TemplateError::TemplateSyntaxError(String::from("`{% if %}` is never closed with `{% endif %}`"), line)),
+                };
+                return Ok(Node::If { branches, otherwise })
+            },
+            ("endif", "") => return Ok(Node::If { branches, otherwise: Vec::new() }),
+            _ => return Err(unexpected_tag(&tag, end_line)),
+        }
+    }
+}
+
+
+fn parse_for(arguments: &str, line: usize, tokens: &mut vec::IntoIter<Token>) -> Result<Node, TemplateError> {
+
+    let (name, source) = match arguments.split_once(" in ") {
+        Some((name, source)) if is_identifier(name.trim()) => (String::from(name.trim()), String::from(source.trim())),
+        _ => return Err(TemplateError::TemplateSyntaxError(String::from("Expected `{% for <name> in <list> %}`"), line)),
+    };
+
+    let iterable = parse_expression(&source, line)?;
+
+    match parse_nodes(tokens)? {
+        (body, Some((tag, _))) if tag == "endfor" => Ok(Node::For { name, iterable, source, line, body }),
+        (_, Some((tag, end_line))) => Err(unexpected_tag(&tag, end_line)),
+        (_, None) => Err(TemplateError::TemplateSyntaxError(String::from("`{% for %}` is never closed with `{% endfor %}`"), line)),
+    }
+}
+
+
+
+
+fn parse_expression(expression: &str, line: usize) -> Result<Expression, TemplateError> {
+
+    let words = split_expression(expression, line)?;
+    let mut words = words.iter().peekable();
+
+    let parsed = parse_or(&mut words, line)?;
+
+    match words.next() {
+        Some(word) => Err(TemplateError::TemplateSyntaxError(format!("Unexpected `{}`", word), line)),
+        None => Ok(parsed),
+    }
+}
+
+
+fn split_expression(expression: &str, line: usize) -> Result<Vec<String>, TemplateError> {
+
+    let mut words = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        chars.next();
+
+        if c.is_whitespace() {
+            continue
+        }
+
+        let word = match c {
+            '"' | '\'' => {
+                let mut word = String::from(c);
+                loop {
+                    match chars.next() {
+                        Some(next) if next == c => break,
+                        Some(next) => word.push(next),
+                        None => return Err(TemplateError::TemplateSyntaxError(String::from("String is never closed"), line)),
+                    }
+                }
+                word.push(c);
+                word
+            },
+            '=' | '!' => match chars.next_if_eq(&'=') {
+                Some(_) => format!("{}=", c),
+                None => return Err(TemplateError::TemplateSyntaxError(format!("Expected `{}=`", c), line)),
+            },
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.next_if(|next| !next.is_whitespace() && !matches!(next, '"' | '\'' | '=' | '!')) {
+                    word.push(next);
+                }
+                word
+            },
+        };
+
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+
+fn parse_or(words: &mut Peekable<slice::Iter<String>>, line: usize) -> Result<Expression, TemplateError> {
+
+    let mut expression = parse_and(words, line)?;
+
+    while words.next_if(|word| *word == "or").is_some() {
+        expression = Expression::Or(Box::new(expression), Box::new(parse_and(words, line)?));
+    }
+
+    Ok(expression)
+}
+
+
+fn parse_and(words: &mut Peekable<slice::Iter<String>>, line: usize) -> Result<Expression, TemplateError> {
+
+    let mut expression = parse_not(words, line)?;
+
+    while words.next_if(|word| *word == "and").is_some() {
+        expression = Expression::And(Box::new(expression), Box::new(parse_not(words, line)?));
+    }
+
+    Ok(expression)
+}
+
+
+fn parse_not(words: &mut Peekable<slice::Iter<String>>, line: usize) -> Result<Expression, TemplateError> {
+
+    if words.next_if(|word| *word == "not").is_some() {
+        return Ok(Expression::Not(Box::new(parse_not(words, line)?)))
+    }
+
+    let left = parse_operand(words, line)?;
+
+    let expression = match words.next_if(|word| *word == "==" || *word == "!=") {
+        Some(operator) if operator == "==" => Expression::Equals(Box::new(left), Box::new(parse_operand(words, line)?)),
+        Some(_) => Expression::NotEquals(Box::new(left), Box::new(parse_operand(words, line)?)),
+        None => left,
+    };
+
+    Ok(expression)
+}
+
+
+fn parse_operand(words: &mut Peekable<slice::Iter<String>>, line: usize) -> Result<Expression, TemplateError> {
+
+    let operand = match words.next().map(String::as_str) {
+        Some(word) if word.starts_with(['"', '\'']) => Expression::Literal(Value::String(String::from(&word[1..word.len() - 1]))),
+        Some("true") => Expression::Literal(Value::Boolean(true)),
+        Some("false") => Expression::Literal(Value::Boolean(false)),
+        Some(word) if word.parse::<i64>().is_ok() => Expression::Literal(Value::Integer(word.parse().unwrap_or_default())),
+        Some(word) if word.split('.').all(is_identifier) => Expression::Variable(String::from(word)),
+        Some(word) => return Err(TemplateError::TemplateSyntaxError(format!("Unexpected `{}`", word), line)),
+        None => return Err(TemplateError::TemplateSyntaxError(String::from("Expected a variable or value"), line)),
+    };
+
+    Ok(operand)
+}
+
+
+fn is_identifier(word: &str) -> bool {
+    !matches!(word, "and" | "or" | "not" | "in")
+        && word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+
+
+
+fn render_nodes(nodes: &[Node], scope: &mut Scope, output: &mut String) -> Result<(), TemplateError> {
+
+    for node in nodes.iter() {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Print(expression, line) => match evaluate(expression, scope, *line)? {
+                Value::String(string) => output.push_str(&string),
+                value => output.push_str(&value.to_string()),
+            },
+            Node::If { branches, otherwise } => {
+                let mut body = otherwise;
+
+                for (condition, line, branch) in branches.iter() {
+                    if is_truthy(&evaluate(condition, scope, *line)?) {
+                        body = branch;
+                        break
+                    }
+                }
+
+                render_nodes(body, scope, output)?;
+            },
+            Node::For { name, iterable, source, line, body } => {
+                let Value::Array(items) = evaluate(iterable, scope, *line)? else {
+                    return Err(TemplateError::NotIterableError(source.clone(), *line))
+                };
+
+                for item in items {
+                    scope.locals.push((name.clone(), item));
+                    let result = render_nodes(body, scope, output);
+                    scope.locals.pop();
+                    result?;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+
+fn evaluate(expression: &Expression, scope: &Scope, line: usize) -> Result<Value, TemplateError> {
+
+    let value = match expression {
+        Expression::Literal(value) => value.clone(),
+        Expression::Variable(name) => match scope.get(name) {
+            Some(value) => value.clone(),
+            None => return Err(TemplateError::UndefinedVariableError(name.clone(), line)),
+        },
+        Expression::Not(inner) => Value::Boolean(!is_truthy(&evaluate(inner, scope, line)?)),
+        Expression::And(left, right) => {
+            Value::Boolean(is_truthy(&evaluate(left, scope, line)?) && is_truthy(&evaluate(right, scope, line)?))
+        },
+        Expression::Or(left, right) => {
+            Value::Boolean(is_truthy(&evaluate(left, scope, line)?) || is_truthy(&evaluate(right, scope, line)?))
+        },
+        Expression::Equals(left, right) => Value::Boolean(evaluate(left, scope, line)? == evaluate(right, scope, line)?),
+        Expression::NotEquals(left, right) => Value::Boolean(evaluate(left, scope, line)? != evaluate(right, scope, line)?),
+    };
+
+    Ok(value)
+}
+
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Boolean(boolean) => *boolean,
+        Value::String(string) => !string.is_empty(),
+        Value::Integer(integer) => *integer != 0,
+        Value::Float(float) => *float != 0.0,
+        Value::Array(array) => !array.is_empty(),
+        Value::Table(table) => !table.is_empty(),
+        Value::Datetime(_) => true,
+    }
+}
+
+
+
+
+#[derive(Debug)]
+pub enum TemplateError {
+    UndefinedVariableError(String, usize),
+    NotIterableError(String, usize),
+    TemplateSyntaxError(String, usize),
+}
+
+impl Error for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UndefinedVariableError(name, line) => {
+                write!(f, "line {}: Undefined variable `{}`", line, name)
+            },
+            TemplateError::NotIterableError(name, line) => {
+                write!(f, "line {}: `{}` is not a list", line, name)
+            },
+            TemplateError::TemplateSyntaxError(message, line) => {
+                write!(f, "line {}: {}", line, message)
+            },
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn render_with(template: &str, variables: &str) -> Result<String, TemplateError> {
+        render(template, &Variables::new(variables.parse().unwrap()))
+    }
+
+
+    #[test]
+    fn if_elif_else() {
+
+        let template = "{% if os == \"linux\" %}linux{% elif os == \"macos\" %}macos{% else %}other{% endif %}";
+
+        assert_eq!(render_with(template, "os = \"linux\"").unwrap(), "linux");
+        assert_eq!(render_with(template, "os = \"macos\"").unwrap(), "macos");
+        assert_eq!(render_with(template, "os = \"freebsd\"").unwrap(), "other");
+    }
+
+
+    #[test]
+    fn conditions() {
+
+        let variables = "a = true\nb = \"\"\nn = 2";
+
+        assert_eq!(render_with("{% if a and not b %}yes{% endif %}", variables).unwrap(), "yes");
+        assert_eq!(render_with("{% if b or n != 2 %}yes{% endif %}", variables).unwrap(), "");
+        assert_eq!(render_with("{% if n == 2 %}yes{% endif %}", variables).unwrap(), "yes");
+    }
+
+
+    #[test]
+    fn nested_for_loops() {
+
+        let template = "{% for row in rows %}{% for cell in row.cells %}{{ cell }}{{ sep }}{% endfor %};{% endfor %}";
+        let variables = "sep = \",\"\nrows = [{ cells = [1, 2] }, { cells = [\"a\"] }]";
+
+        assert_eq!(render_with(template, variables).unwrap(), "1,2,;a,;");
+    }
+
+
+    #[test]
+    fn loop_variables_go_out_of_scope() {
+
+        let result = render_with("{% for item in items %}{% endfor %}{{ item }}", "items = [1]");
+
+        assert!(matches!(result, Err(TemplateError::UndefinedVariableError(name, 1)) if name == "item"));
+    }
+
+
+    #[test]
+    fn standalone_tags_leave_no_empty_lines() {
+
+        let template = "a\n{% if x %}\n  {# comment #}\nb\n{% endif %}\nc\n";
+
+        assert_eq!(render_with(template, "x = true").unwrap(), "a\nb\nc\n");
+        assert_eq!(render_with(template, "x = false").unwrap(), "a\nc\n");
+    }
+
+
+    #[test]
+    fn inline_tags_keep_their_line() {
+
+        assert_eq!(render_with("a {% if x %}b{% endif %} c\n", "x = true").unwrap(), "a b c\n");
+        assert_eq!(render_with("{{ x }}\n{% if x %}\ny\n{% endif %}", "x = 1").unwrap(), "1\ny\n");
+    }
+
+
+    #[test]
+    fn errors_report_their_line() {
+
+        let result = render_with("a\n{# two\nlines #}\nb {{ missing }}", "");
+        assert!(matches!(result, Err(TemplateError::UndefinedVariableError(name, 4)) if name == "missing"));
+
+        let result = render_with("a\n{% if x %}\n{% endif %}\n{% for i in x %}{% endfor %}", "x = 1");
+        assert!(matches!(result, Err(TemplateError::NotIterableError(name, 4)) if name == "x"));
+
+        let result = render_with("a\n\n{% endif %}", "");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(_, 3))));
+    }
+
+
+    #[test]
+    fn unclosed_tags() {
+
+        let result = render_with("a\n{{ x", "x = 1");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(message, 2)) if message == "`{{` is never closed"));
+
+        let result = render_with("{% if x %}\na", "x = 1");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(message, 1)) if message.contains("endif")));
+
+        let result = render_with("\n{% for i in x %}a", "x = [1]");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(message, 2)) if message.contains("endfor")));
+
+        let result = render_with("{% if x %}{% else %}{% endfor %}", "x = 1");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(message, 1)) if message.contains("endfor")));
+    }
+
+
+    // `{#` starts a comment anywhere, shell like `${#array[@]}` has to print it as a string instead
+    #[test]
+    fn comment_opening_in_shell_text() {
+
+        let result = render_with("echo ${#array[@]}", "");
+        assert!(matches!(result, Err(TemplateError::TemplateSyntaxError(message, 1)) if message == "`{#` is never closed"));
+
+        assert_eq!(render_with("echo ${{ \"{#\" }}array[@]}", "").unwrap(), "echo ${#array[@]}");
+    }
+}
@@ -0,0 +1,79 @@
+use std::env;
+
+use toml::{Table, Value};
+
+
+
+
+#[derive(Default, Clone)]
+pub struct Variables {
+    values: Table,
+}
+
+impl Variables {
+    pub fn new(values: Table) -> Variables {
+        Variables { values }
+    }
+
+
+    pub fn facts() -> Variables {
+
+        let mut values = Table::new();
+
+        values.insert(String::from("hostname"), Value::String(hostname()));
+        values.insert(String::from("username"), Value::String(username()));
+        values.insert(String::from("os"), Value::String(String::from(env::consts::OS)));
+        values.insert(String::from("arch"), Value::String(String::from(env::consts::ARCH)));
+
+        Variables { values }
+    }
+
+
+    pub fn merge(&mut self, overrides: &Table) {
+        merge_tables(&mut self.values, overrides);
+    }
+
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+
+        let mut keys = name.split('.');
+
+        let mut value = self.values.get(keys.next()?)?;
+
+        for key in keys {
+            value = value.as_table()?.get(key)?;
+        }
+
+        Some(value)
+    }
+}
+
+
+
+
+fn merge_tables(values: &mut Table, overrides: &Table) {
+
+    for (key, value) in overrides.iter() {
+        match (values.get_mut(key), value) {
+            (Some(Value::Table(table)), Value::Table(override_table)) => merge_tables(table, override_table),
+            _ => {
+                values.insert(key.clone(), value.clone());
+            },
+        }
+    }
+}
+
+
+pub fn hostname() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_default()
+}
+
+
+pub fn username() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default()
+}
@@ -0,0 +1,326 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::error::Error;
+use std::fmt;
+
+
+
+
+#[derive(Clone)]
+pub struct GitSettings {
+    pub auto_commit: bool,
+    pub pull: bool,
+    pub remote: String,
+    pub branch: Option<String>,
+}
+
+impl Default for GitSettings {
+    fn default() -> Self {
+        GitSettings { auto_commit: true, pull: false, remote: String::from("origin"), branch: None }
+    }
+}
+
+
+
+
+pub struct GitRepo {
+    pub path: PathBuf,
+}
+
+impl GitRepo {
+    pub fn open(path: &Path) -> Option<GitRepo> {
+
+        if !path.is_dir() {
+            return None
+        }
+
+        let repo = GitRepo { path: path.to_path_buf() };
+
+        match repo.run(&["rev-parse", "--is-inside-work-tree"]) {
+            Ok(output) if output.status.success() => Some(repo),
+            _ => None,
+        }
+    }
+
+
+    fn run(&self, args: &[&str]) -> Result<Output, GitError> {
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.path)
+            .args(args)
+            .output()?;
+
+        Ok(output)
+    }
+
+
+    fn git(&self, args: &[&str]) -> Result<String, GitError> {
+
+        let output = self.run(args)?;
+
+        if !output.status.success() {
+            return Err(GitError::GitCommandError {
+                command: format!("git {}", args.first().unwrap_or(&"")),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            })
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+
+    pub fn is_dirty(&self) -> Result<bool, GitError> {
+        Ok(!self.git(&["status", "--porcelain", "--untracked-files=no"])?.trim().is_empty())
+    }
+
+
+    pub fn commit(&self, paths: &[PathBuf], message: &str) -> Result<bool, GitError> {
+
+        let paths: Vec<&str> = paths.iter().filter_map(|path| path.to_str()).collect();
+
+        let mut add_args = vec!["add", "-A", "--"];
+        add_args.extend(paths.iter());
+        self.git(&add_args)?;
+
+        let mut diff_args = vec!["diff", "--cached", "--quiet", "--"];
+        diff_args.extend(paths.iter());
+        if self.run(&diff_args)?.status.success() {
+            return Ok(false)
+        }
+
+        let mut commit_args = vec!["commit", "-m", message, "--"];
+        commit_args.extend(paths.iter());
+        self.git(&commit_args)?;
+
+        Ok(true)
+    }
+
+
+    pub fn pull(&self, remote: &str, branch: Option<&str>) -> Result<(), GitError> {
+
+        if self.is_dirty()? {
+            return Err(GitError::DirtyWorkingTreeError(self.path.clone()))
+        }
+
+        let branch = match branch {
+            Some(branch) => String::from(branch),
+            None => self.git(&["rev-parse", "--abbrev-ref", "HEAD"])?.trim().to_string(),
+        };
+
+        self.git(&["fetch", remote, &branch])?;
+
+        if let Err(rebase_error) = self.git(&["rebase", "FETCH_HEAD"]) {
+            let conflicts: Vec<String> = self.git(&["diff", "--name-only", "--diff-filter=U"])
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect();
+
+            let _ = self.git(&["rebase", "--abort"]);
+
+            if conflicts.is_empty() {
+                return Err(rebase_error)
+            }
+
+            return Err(GitError::MergeConflictError(conflicts))
+        }
+
+        Ok(())
+    }
+}
+
+
+
+
+#[derive(Debug)]
+pub enum GitError {
+    GitIOError(std::io::Error),
+    GitCommandError { command: String, stderr: String },
+    DirtyWorkingTreeError(PathBuf),
+    MergeConflictError(Vec<String>),
+}
+
+impl Error for GitError {}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::GitIOError(io_error) => {
+                write!(f, "Failed to run git: {}", io_error)
+            },
+            GitError::GitCommandError { command, stderr } => {
+                write!(f, "{} failed: {}", command, stderr)
+            },
+            GitError::DirtyWorkingTreeError(path) => {
+                write!(f, "{} has uncommitted changes, commit or stash them before pulling", path.display())
+            },
+            GitError::MergeConflictError(paths) => {
+                write!(f, "Pulling caused merge conflicts, the rebase was aborted:")?;
+                for path in paths.iter() {
+                    write!(f, "\n  {}", path)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for GitError {
+    fn from(error: std::io::Error) -> GitError {
+        GitError::GitIOError(error)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+
+        let output = Command::new("git").arg("-C").arg(dir).args(args).output().unwrap();
+
+        assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+
+    fn configure(dir: &Path) {
+        git(dir, &["config", "user.name", "Test"]);
+        git(dir, &["config", "user.email", "test@example.com"]);
+        git(dir, &["config", "commit.gpgsign", "false"]);
+    }
+
+
+    fn commit_file(dir: &Path, name: &str, contents: &str, message: &str) {
+        fs::write(dir.join(name), contents).unwrap();
+        git(dir, &["add", name]);
+        git(dir, &["commit", "-q", "-m", message]);
+    }
+
+
+    // A bare remote with two clones of it, the manager folder and another machine pushing to it
+    fn setup(name: &str) -> (PathBuf, GitRepo, PathBuf) {
+
+        let root = env::temp_dir().join(format!("dotfiles-git-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        git(&root, &["init", "-q", "--bare", "-b", "main", "remote.git"]);
+
+        let manager_dir = root.join("manager");
+        git(&root, &["init", "-q", "-b", "main", "manager"]);
+        configure(&manager_dir);
+        commit_file(&manager_dir, "zshrc", "initial\n", "Initial");
+        git(&manager_dir, &["remote", "add", "origin", "../remote.git"]);
+        git(&manager_dir, &["push", "-q", "-u", "origin", "main"]);
+
+        let other_dir = root.join("other");
+        git(&root, &["clone", "-q", "remote.git", "other"]);
+        configure(&other_dir);
+
+        let repo = GitRepo::open(&manager_dir).unwrap();
+
+        (root, repo, other_dir)
+    }
+
+
+    #[test]
+    fn open_only_finds_repositories() {
+
+        let (root, repo, _) = setup("open");
+
+        assert!(GitRepo::open(&repo.path).is_some());
+        assert!(GitRepo::open(&root).is_none());
+        assert!(GitRepo::open(&root.join("missing")).is_none());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+
+    #[test]
+    fn commit_after_collect() {
+
+        let (root, repo, _) = setup("commit");
+
+        fs::write(repo.path.join("zshrc"), "collected\n").unwrap();
+        fs::write(repo.path.join("untouched"), "not collected\n").unwrap();
+
+        assert!(repo.commit(&[repo.path.join("zshrc")], "Collect zshrc").unwrap());
+        assert_eq!(git(&repo.path, &["log", "-1", "--format=%s"]), "Collect zshrc");
+        assert_eq!(git(&repo.path, &["show", "--name-only", "--format=", "HEAD"]), "zshrc");
+
+        // Nothing changed since, so there is nothing to commit
+        assert!(!repo.commit(&[repo.path.join("zshrc")], "Collect zshrc").unwrap());
+        assert_eq!(git(&repo.path, &["rev-list", "--count", "HEAD"]), "2");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+
+    #[test]
+    fn pull_rebases_onto_the_remote() {
+
+        let (root, repo, other_dir) = setup("pull");
+
+        commit_file(&other_dir, "vimrc", "from another machine\n", "Collect vimrc");
+        git(&other_dir, &["push", "-q"]);
+
+        commit_file(&repo.path, "zshrc", "local\n", "Collect zshrc");
+
+        repo.pull("origin", None).unwrap();
+
+        assert_eq!(fs::read_to_string(repo.path.join("vimrc")).unwrap(), "from another machine\n");
+        assert_eq!(git(&repo.path, &["log", "--format=%s"]), "Collect zshrc\nCollect vimrc\nInitial");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+
+    #[test]
+    fn pull_refuses_a_dirty_tree() {
+
+        let (root, repo, _) = setup("dirty");
+
+        fs::write(repo.path.join("zshrc"), "uncommitted\n").unwrap();
+
+        assert!(repo.is_dirty().unwrap());
+        assert!(matches!(repo.pull("origin", Some("main")), Err(GitError::DirtyWorkingTreeError(path)) if path == repo.path));
+        assert_eq!(fs::read_to_string(repo.path.join("zshrc")).unwrap(), "uncommitted\n");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+
+    #[test]
+    fn pull_aborts_on_conflicts() {
+
+        let (root, repo, other_dir) = setup("conflict");
+
+        commit_file(&other_dir, "zshrc", "remote\n", "Collect zshrc remotely");
+        git(&other_dir, &["push", "-q"]);
+
+        commit_file(&repo.path, "zshrc", "local\n", "Collect zshrc locally");
+        let head = git(&repo.path, &["rev-parse", "HEAD"]);
+
+        let result = repo.pull("origin", None);
+
+        assert!(matches!(result, Err(GitError::MergeConflictError(paths)) if paths == ["zshrc"]));
+
+        // The rebase was aborted, leaving the manager folder as it was
+        assert_eq!(git(&repo.path, &["rev-parse", "HEAD"]), head);
+        assert_eq!(fs::read_to_string(repo.path.join("zshrc")).unwrap(), "local\n");
+        assert!(!repo.is_dirty().unwrap());
+        assert!(!repo.path.join(".git/rebase-merge").exists() && !repo.path.join(".git/rebase-apply").exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}